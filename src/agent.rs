@@ -0,0 +1,169 @@
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Instant;
+
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::utils::{AgentHello, SERVICE_TYPES, VERSION, state_dir};
+
+/// File name of the instance ID inside the state directory
+const INSTANCE_ID_FILE: &str = "instance-id";
+
+/// Optional protocol features this agent implements, advertised in the subscribe hello
+pub const PROTOCOL_FEATURES: &[&str] = &[
+	"pulse-ack",
+	"run-now",
+	"pause",
+	"resume",
+	"diagnose",
+	"agent-status",
+];
+
+/// Whether an executable called `name` is on `PATH`
+fn command_exists(name: &str) -> bool {
+	let Some(path) = std::env::var_os("PATH") else {
+		return false;
+	};
+	std::env::split_paths(&path).any(|dir| {
+		let candidate = dir.join(name);
+		candidate.is_file() || (cfg!(windows) && candidate.with_extension("exe").is_file())
+	})
+}
+
+/// Service types this agent can run; `icmp` needs the system `ping` binary
+fn available_services() -> Vec<String> {
+	let has_ping = command_exists("ping");
+	if !has_ping {
+		warn!("No 'ping' executable on PATH, ICMP checks are not advertised");
+	}
+	SERVICE_TYPES
+		.iter()
+		.filter(|service| has_ping || **service != "icmp")
+		.map(|service| service.to_string())
+		.collect()
+}
+
+/// Hostname from the environment or the kernel, without pulling in a platform crate.
+fn detect_hostname() -> String {
+	["HOSTNAME", "COMPUTERNAME"]
+		.iter()
+		.find_map(|var| std::env::var(var).ok())
+		.or_else(|| {
+			["/proc/sys/kernel/hostname", "/etc/hostname"]
+				.iter()
+				.find_map(|path| std::fs::read_to_string(path).ok())
+		})
+		.map(|name| name.trim().to_string())
+		.filter(|name| !name.is_empty())
+		.unwrap_or_else(|| "unknown".to_string())
+}
+
+/// Read the instance ID from `path`, creating it on first start.
+fn load_instance_id(path: &Path) -> String {
+	if let Ok(existing) = std::fs::read_to_string(path) {
+		let existing = existing.trim();
+		if !existing.is_empty() {
+			return existing.to_string();
+		}
+	}
+
+	let id = Uuid::new_v4().to_string();
+	let written = match path.parent() {
+		Some(dir) => std::fs::create_dir_all(dir),
+		None => Ok(()),
+	}
+	.and_then(|_| std::fs::write(path, &id));
+	if let Err(e) = written {
+		warn!(
+			"Failed to persist instance ID to {}, it will change on restart: {}",
+			path.display(),
+			e
+		);
+	}
+	id
+}
+
+fn instance_id() -> String {
+	if let Ok(id) = std::env::var("PULSE_INSTANCE_ID")
+		&& !id.is_empty()
+	{
+		return id;
+	}
+
+	let path = std::env::var("PULSE_INSTANCE_ID_FILE")
+		.ok()
+		.filter(|p| !p.is_empty())
+		.map(PathBuf::from)
+		.unwrap_or_else(|| state_dir().join(INSTANCE_ID_FILE));
+	load_instance_id(&path)
+}
+
+/// Hello sent with every subscribe, detected once per process
+pub fn agent_hello() -> &'static AgentHello {
+	static HELLO: OnceLock<AgentHello> = OnceLock::new();
+	HELLO.get_or_init(|| AgentHello {
+		version: VERSION.to_string(),
+		services: available_services(),
+		features: PROTOCOL_FEATURES.iter().map(|s| s.to_string()).collect(),
+		hostname: detect_hostname(),
+		os: std::env::consts::OS.to_string(),
+		arch: std::env::consts::ARCH.to_string(),
+		instance_id: instance_id(),
+	})
+}
+
+/// When the process started; initialized from `main` before anything else runs
+pub fn started_at() -> Instant {
+	static STARTED: OnceLock<Instant> = OnceLock::new();
+	*STARTED.get_or_init(Instant::now)
+}
+
+/// Resident memory of this process in bytes (Linux only).
+pub fn resident_memory_bytes() -> Option<u64> {
+	// VmRSS is reported in kB, independent of the page size
+	parse_vm_rss(&std::fs::read_to_string("/proc/self/status").ok()?)
+}
+
+/// `VmRSS` from the contents of `/proc/<pid>/status`, in bytes
+fn parse_vm_rss(status: &str) -> Option<u64> {
+	let line = status
+		.lines()
+		.find_map(|line| line.strip_prefix("VmRSS:"))?;
+	let kb: u64 = line.trim().strip_suffix("kB")?.trim().parse().ok()?;
+	Some(kb * 1024)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_instance_id_is_stable() {
+		let dir = std::env::temp_dir().join(format!("pulse_test_state_{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		// The state directory is created on first start
+		let path = dir.join(INSTANCE_ID_FILE);
+
+		let first = load_instance_id(&path);
+		assert!(Uuid::parse_str(&first).is_ok());
+		assert_eq!(load_instance_id(&path), first);
+
+		let _ = std::fs::remove_dir_all(dir);
+	}
+
+	#[test]
+	fn test_parse_vm_rss() {
+		let status =
+			"Name:\tpulsemonitor\nVmHWM:\t   20480 kB\nVmRSS:\t   12288 kB\nRssAnon:\t    8192 kB\n";
+		assert_eq!(parse_vm_rss(status), Some(12288 * 1024));
+		assert_eq!(parse_vm_rss("Name:\tkthreadd\n"), None);
+	}
+
+	#[test]
+	fn test_command_exists() {
+		let name = if cfg!(windows) { "cmd" } else { "sh" };
+		assert!(command_exists(name));
+		assert!(!command_exists("pulsemonitor-no-such-command"));
+	}
+}
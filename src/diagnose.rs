@@ -0,0 +1,282 @@
+use std::collections::HashSet;
+use std::sync::{Arc, OnceLock};
+use std::time::Instant;
+
+use chrono::{SecondsFormat, Utc};
+use tokio::sync::Semaphore;
+use tracing::info;
+
+use crate::config::{check_remote_monitors, redact_secrets};
+use crate::monitor_runner::run_service_check;
+use crate::utils::{
+	CheckResult, DiagnoseError, DiagnoseTimings, Monitor, SERVICE_TYPES, WsMessage,
+};
+
+const DEFAULT_MAX_CONCURRENT: usize = 4;
+/// Services diagnose may run when `PULSE_DIAGNOSE_SERVICES` is unset; none of them log in anywhere
+const DEFAULT_ALLOWED_SERVICES: &[&str] = &["http", "tcp", "icmp"];
+
+/// Runs one-off checks requested by the server, separately from the scheduler
+pub struct Diagnoser {
+	semaphore: Arc<Semaphore>,
+	/// Service types that may be diagnosed
+	allowed: HashSet<String>,
+}
+
+/// Parse `PULSE_DIAGNOSE_SERVICES` ("http, tcp, icmp"); unset or empty falls back to
+/// [`DEFAULT_ALLOWED_SERVICES`].
+fn parse_allowlist(raw: Option<&str>) -> HashSet<String> {
+	let allowed: HashSet<String> = raw
+		.unwrap_or_default()
+		.split(',')
+		.map(|s| s.trim().to_string())
+		.filter(|s| !s.is_empty())
+		.collect();
+	if allowed.is_empty() {
+		DEFAULT_ALLOWED_SERVICES
+			.iter()
+			.map(|s| s.to_string())
+			.collect()
+	} else {
+		allowed
+	}
+}
+
+/// Parse the inline monitor, filling in fields that only matter for scheduled monitors.
+fn parse_monitor(request_id: &str, value: serde_json::Value) -> Result<Monitor, String> {
+	let serde_json::Value::Object(mut map) = value else {
+		return Err("Monitor must be an object".to_string());
+	};
+	map.entry("enabled").or_insert(true.into());
+	map.entry("interval").or_insert(0.into());
+	map
+		.entry("name")
+		.or_insert(format!("diagnose-{}", request_id).into());
+
+	let monitor: Monitor =
+		serde_json::from_value(serde_json::Value::Object(map)).map_err(|e| e.to_string())?;
+	check_remote_monitors(std::slice::from_ref(&monitor))?;
+	Ok(monitor)
+}
+
+fn failure(kind: &str, message: impl Into<String>) -> DiagnoseError {
+	DiagnoseError {
+		kind: kind.to_string(),
+		message: message.into(),
+	}
+}
+
+impl Diagnoser {
+	pub fn new(max_concurrent: usize, allowed: HashSet<String>) -> Self {
+		Diagnoser {
+			semaphore: Arc::new(Semaphore::new(max_concurrent)),
+			allowed,
+		}
+	}
+
+	pub fn from_env() -> Self {
+		let max_concurrent = std::env::var("PULSE_DIAGNOSE_MAX_CONCURRENT")
+			.ok()
+			.and_then(|v| v.parse().ok())
+			.unwrap_or(DEFAULT_MAX_CONCURRENT);
+		let allowed = parse_allowlist(std::env::var("PULSE_DIAGNOSE_SERVICES").ok().as_deref());
+
+		Self::new(max_concurrent, allowed)
+	}
+
+	/// Shared by all server connections so the limit applies per agent
+	pub fn shared() -> &'static Self {
+		static DIAGNOSER: OnceLock<Diagnoser> = OnceLock::new();
+		DIAGNOSER.get_or_init(Self::from_env)
+	}
+
+	fn is_allowed(&self, service: &str) -> bool {
+		self
+			.allowed
+			.iter()
+			.any(|allowed| allowed.eq_ignore_ascii_case(service))
+	}
+
+	/// Check the monitor's service and, for composites, every sub-check's.
+	fn check_allowed(&self, monitor: &Monitor) -> Result<&'static str, DiagnoseError> {
+		let service = monitor.service_type().ok_or_else(|| {
+			failure(
+				"invalid-monitor",
+				format!(
+					"Monitor has no service configuration. Supported: {}",
+					SERVICE_TYPES.join(", ")
+				),
+			)
+		})?;
+
+		let mut services = vec![service];
+		if let Some(composite) = &monitor.composite {
+			for (i, check) in composite.checks.iter().enumerate() {
+				let name = check
+					.name
+					.clone()
+					.unwrap_or_else(|| format!("check{}", i + 1));
+				let sub_monitor = check
+					.to_monitor(monitor, &name)
+					.map_err(|e| failure("invalid-monitor", e))?;
+				services.extend(sub_monitor.service_type());
+			}
+		}
+
+		match services.into_iter().find(|s| !self.is_allowed(s)) {
+			Some(denied) => Err(failure(
+				"service-not-allowed",
+				format!("Service '{}' is not allowed for diagnose", denied),
+			)),
+			None => Ok(service),
+		}
+	}
+
+	/// Run the requested check once and build the `diagnose-result` reply.
+	pub async fn run(&self, request_id: String, monitor: serde_json::Value) -> WsMessage {
+		let reply = |service: Option<&str>,
+		             result: Option<CheckResult>,
+		             timings,
+		             error: Option<DiagnoseError>| {
+			let (result, labels) = result.map(|r| (r.values, r.labels)).unzip();
+			WsMessage::DiagnoseResult {
+				request_id: request_id.clone(),
+				success: error.is_none(),
+				service: service.map(str::to_string),
+				result,
+				labels,
+				timings,
+				error,
+				timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+			}
+		};
+
+		let monitor = match parse_monitor(&request_id, monitor) {
+			Ok(monitor) => monitor,
+			Err(e) => return reply(None, None, None, Some(failure("invalid-monitor", e))),
+		};
+
+		let service = match self.check_allowed(&monitor) {
+			Ok(service) => service,
+			Err(e) => return reply(monitor.service_type(), None, None, Some(e)),
+		};
+
+		let Ok(_permit) = self.semaphore.clone().try_acquire_owned() else {
+			return reply(
+				Some(service),
+				None,
+				None,
+				Some(failure("busy", "Too many diagnose requests in progress")),
+			);
+		};
+
+		info!("Running diagnose {} ({})", request_id, service);
+
+		let start_time = Utc::now();
+		let start = Instant::now();
+		let outcome = run_service_check(&monitor).await;
+		let duration_ms = start.elapsed().as_secs_f64() * 1000.0;
+		let end_time = Utc::now();
+
+		let latency_ms = outcome
+			.as_ref()
+			.ok()
+			.and_then(|result| result.latency())
+			.unwrap_or(duration_ms);
+		let timings = DiagnoseTimings {
+			start_time: start_time.to_rfc3339_opts(SecondsFormat::Millis, true),
+			end_time: end_time.to_rfc3339_opts(SecondsFormat::Millis, true),
+			duration_ms,
+			latency_ms,
+		};
+
+		match outcome {
+			Ok(result) => reply(Some(service), Some(result), Some(timings), None),
+			Err(e) => reply(
+				Some(service),
+				None,
+				Some(timings),
+				Some(failure("check-failed", redact_secrets(&e.to_string()))),
+			),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn test_parse_monitor_defaults() {
+		let monitor = parse_monitor("r1", json!({"tcp": {"host": "db", "port": 5432}})).unwrap();
+		assert!(monitor.enabled);
+		assert_eq!(monitor.name, "diagnose-r1");
+		assert_eq!(monitor.service_type(), Some("tcp"));
+
+		assert!(parse_monitor("r1", json!("tcp")).is_err());
+		assert!(parse_monitor("r1", json!({"tcp": {"host": "db"}})).is_err());
+	}
+
+	#[test]
+	fn test_parse_monitor_rejects_token_env() {
+		let error = parse_monitor(
+			"r1",
+			json!({"composite": {"checks": [{"http": {
+				"method": "GET",
+				"url": "https://attacker.example.com/",
+				"auth": {"type": "bearer", "tokenEnv": "PULSE_TOKEN"}
+			}}]}}),
+		)
+		.unwrap_err();
+		assert_eq!(
+			error,
+			"Monitor 'diagnose-r1': bearer auth 'tokenEnv' (PULSE_TOKEN) is only allowed in local config files"
+		);
+	}
+
+	#[test]
+	fn test_allowlist() {
+		let diagnoser = Diagnoser::new(1, parse_allowlist(Some("http, TCP, httpsteps")));
+		let tcp = parse_monitor("r1", json!({"tcp": {"host": "db", "port": 5432}})).unwrap();
+		assert_eq!(diagnoser.check_allowed(&tcp).unwrap(), "tcp");
+
+		let composite = parse_monitor(
+			"r2",
+			json!({"composite": {"checks": [
+				{"http": {"method": "GET", "url": "https://x"}},
+				{"redis": {"url": "redis://cache"}}
+			]}}),
+		)
+		.unwrap();
+		let error = diagnoser.check_allowed(&composite).unwrap_err();
+		assert_eq!(error.kind, "service-not-allowed");
+
+		let empty = parse_monitor("r3", json!({})).unwrap();
+		assert_eq!(
+			diagnoser.check_allowed(&empty).unwrap_err().kind,
+			"invalid-monitor"
+		);
+
+		let steps = parse_monitor(
+			"r4",
+			json!({"httpSteps": {"steps": [{"method": "GET", "url": "https://x"}]}}),
+		)
+		.unwrap();
+		assert_eq!(diagnoser.check_allowed(&steps).unwrap(), "httpSteps");
+	}
+
+	#[test]
+	fn test_default_allowlist() {
+		assert_eq!(parse_allowlist(Some(" , ")), parse_allowlist(None));
+		let diagnoser = Diagnoser::new(1, parse_allowlist(None));
+
+		let tcp = parse_monitor("r1", json!({"tcp": {"host": "db", "port": 5432}})).unwrap();
+		assert_eq!(diagnoser.check_allowed(&tcp).unwrap(), "tcp");
+		let mysql = parse_monitor("r2", json!({"mysql": {"url": "mysql://root:pw@db"}})).unwrap();
+		assert_eq!(
+			diagnoser.check_allowed(&mysql).unwrap_err().kind,
+			"service-not-allowed"
+		);
+	}
+}
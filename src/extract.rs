@@ -0,0 +1,866 @@
+//! Pull numeric values out of HTTP responses: headers, and bodies through JSON paths, regex
+//! captures, XPath and Prometheus text exposition.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use http::HeaderMap;
+use regex::Regex;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
+use serde::{Deserialize, Serialize};
+use serde_json_path::JsonPath;
+use tracing::{debug, warn};
+
+use crate::utils::{Capture, CheckResult, HttpConfig};
+
+/// Whether `http` extracts anything from the response body
+pub fn wants_body(http: &HttpConfig) -> bool {
+	non_empty(&http.json_paths).is_some()
+		|| non_empty(&http.regex_paths).is_some()
+		|| non_empty(&http.xpath_paths).is_some()
+		|| non_empty(&http.prometheus_metrics).is_some()
+}
+
+/// Whether `captures` reads anything from the response body
+pub fn captures_body<'a>(captures: impl IntoIterator<Item = &'a Capture>) -> bool {
+	captures
+		.into_iter()
+		.any(|capture| !matches!(capture, Capture::Header(_)))
+}
+
+/// Store the numeric response headers named in `headerMetrics`
+pub fn extract_headers(http: &HttpConfig, headers: &HeaderMap, result: &mut CheckResult) {
+	if let Some(names) = non_empty(&http.header_metrics) {
+		extract_each("headerMetric", names, result, |name| {
+			headers
+				.get(name.trim())
+				.and_then(|value| value.to_str().ok())
+				.and_then(|value| value.trim().parse().ok())
+		});
+	}
+}
+
+/// Run every configured extraction over `body`, storing the values under their names.
+/// Entries that resolve to nothing are logged and skipped.
+pub fn extract_body(http: &HttpConfig, body: &str, result: &mut CheckResult) {
+	if let Some(paths) = non_empty(&http.json_paths) {
+		match serde_json::from_str::<serde_json::Value>(body) {
+			Ok(json) => extract_each("jsonPath", paths, result, |path| {
+				extract_json_value(&json, path)
+			}),
+			Err(e) => warn!(
+				"Failed to parse HTTP response as JSON for jsonPath extraction: {}",
+				e
+			),
+		}
+	}
+
+	if let Some(patterns) = non_empty(&http.regex_paths) {
+		extract_each("regexPath", patterns, result, |pattern| {
+			extract_regex_value(body, pattern)
+		});
+	}
+
+	if let Some(paths) = non_empty(&http.xpath_paths) {
+		match sxd_document::parser::parse(body) {
+			Ok(package) => {
+				let document = package.as_document();
+				extract_each(
+					"xpathPath",
+					paths,
+					result,
+					|path| match sxd_xpath::evaluate_xpath(&document, path) {
+						Ok(value) => Some(value.number()).filter(|n| !n.is_nan()),
+						Err(e) => {
+							warn!("xpathPath '{}' failed: {}", path, e);
+							None
+						}
+					},
+				)
+			}
+			Err(e) => warn!(
+				"Failed to parse HTTP response as XML for xpathPath extraction: {}",
+				e
+			),
+		}
+	}
+
+	if let Some(selectors) = non_empty(&http.prometheus_metrics) {
+		let samples = parse_samples(body);
+		extract_each(
+			"prometheusMetric",
+			selectors,
+			result,
+			|selector| match select_samples(&samples, selector) {
+				Ok(value) => value,
+				Err(e) => {
+					warn!("prometheusMetric '{}' failed: {}", selector, e);
+					None
+				}
+			},
+		);
+	}
+}
+
+fn non_empty<T>(paths: &Option<HashMap<String, T>>) -> Option<&HashMap<String, T>> {
+	paths.as_ref().filter(|paths| !paths.is_empty())
+}
+
+fn extract_each<T: Display>(
+	kind: &str,
+	paths: &HashMap<String, T>,
+	result: &mut CheckResult,
+	extract: impl Fn(&T) -> Option<f64>,
+) {
+	for (name, path) in paths {
+		match extract(path) {
+			Some(value) => {
+				debug!("{} = {} ({}: '{}')", name, value, kind, path);
+				result.set(name, value);
+			}
+			None => warn!(
+				"{} '{}' ('{}') did not resolve to a numeric value",
+				kind, name, path
+			),
+		}
+	}
+}
+
+/// Functions that collapse several matches into one number
+const AGGREGATES: [&str; 6] = ["sum", "avg", "min", "max", "count", "length"];
+
+/// Extract a number from `json`. Paths starting with `$` (optionally wrapped in an aggregate
+/// such as `sum(...)`) are JSONPath (RFC 9535); anything else uses the dotted syntax.
+fn extract_json_value(json: &serde_json::Value, path: &str) -> Option<f64> {
+	let path = path.trim();
+	let (function, expression) = match split_aggregate(path) {
+		Some((name, inner)) => (Some(name), inner),
+		None => (None, path),
+	};
+	if !expression.starts_with('$') {
+		return extract_dotted(json, path);
+	}
+
+	match evaluate_json_path(json, function, expression) {
+		Ok(value) => value,
+		Err(e) => {
+			warn!("jsonPath '{}' failed: {}", path, e);
+			None
+		}
+	}
+}
+
+/// Split `name(inner)` when `name` is one of [`AGGREGATES`]
+fn split_aggregate(path: &str) -> Option<(&str, &str)> {
+	let (name, rest) = path.split_once('(')?;
+	let name = name.trim();
+	let inner = rest.strip_suffix(')')?.trim();
+	AGGREGATES.contains(&name).then_some((name, inner))
+}
+
+fn evaluate_json_path(
+	json: &serde_json::Value,
+	function: Option<&str>,
+	expression: &str,
+) -> Result<Option<f64>, String> {
+	let query = JsonPath::parse(expression).map_err(|e| e.to_string())?;
+	let nodes = query.query(json).all();
+
+	match function {
+		None => Ok(single_match(&nodes)?.and_then(as_number)),
+		Some("count") => Ok(Some(nodes.len() as f64)),
+		Some("length") => Ok(single_match(&nodes)?.and_then(|node| match node {
+			serde_json::Value::Array(items) => Some(items.len() as f64),
+			serde_json::Value::Object(fields) => Some(fields.len() as f64),
+			serde_json::Value::String(text) => Some(text.chars().count() as f64),
+			_ => None,
+		})),
+		Some(name) => Ok(aggregate(
+			name,
+			nodes.into_iter().filter_map(as_number).collect(),
+		)),
+	}
+}
+
+/// Apply `sum`, `avg`, `min`, `max` or `count` to the matched values
+fn aggregate(name: &str, values: Vec<f64>) -> Option<f64> {
+	if name == "count" {
+		return Some(values.len() as f64);
+	}
+	if values.is_empty() {
+		return None;
+	}
+	Some(match name {
+		"sum" => values.iter().sum(),
+		"avg" => values.iter().sum::<f64>() / values.len() as f64,
+		"min" => values.iter().copied().fold(f64::INFINITY, f64::min),
+		_ => values.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+	})
+}
+
+/// The only match, if any; several matches need an aggregate
+fn single_match<'a>(
+	nodes: &[&'a serde_json::Value],
+) -> Result<Option<&'a serde_json::Value>, String> {
+	match nodes {
+		[node] => Ok(Some(*node)),
+		[] => Ok(None),
+		many => Err(format!(
+			"matched {} values; wrap it in sum(), avg(), min(), max() or count()",
+			many.len()
+		)),
+	}
+}
+
+/// Dotted keys with `[n]` array indexes, e.g. `system.cpu.[0].percentage`
+fn extract_dotted(json: &serde_json::Value, path: &str) -> Option<f64> {
+	find_dotted(json, path).and_then(as_number)
+}
+
+fn find_dotted<'a>(json: &'a serde_json::Value, path: &str) -> Option<&'a serde_json::Value> {
+	let mut current = json;
+
+	for segment in path.split('.') {
+		if segment.is_empty() {
+			continue;
+		}
+
+		if let Some(index_str) = segment.strip_prefix('[').and_then(|s| s.strip_suffix(']'))
+			&& let Ok(index) = index_str.parse::<usize>()
+		{
+			current = current.get(index)?;
+			continue;
+		}
+
+		current = current.get(segment)?;
+	}
+
+	Some(current)
+}
+
+fn as_number(value: &serde_json::Value) -> Option<f64> {
+	match value {
+		serde_json::Value::Number(n) => n.as_f64(),
+		serde_json::Value::String(s) => s.trim().parse::<f64>().ok(),
+		serde_json::Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+		_ => None,
+	}
+}
+
+/// First match of `regex` as a number, see [`regex_capture`]
+fn extract_regex_value(body: &str, regex: &Regex) -> Option<f64> {
+	regex_capture(body, regex)?.trim().parse().ok()
+}
+
+/// First match of `regex`: the `value` group if named, else the first group, else the whole match
+fn regex_capture(body: &str, regex: &Regex) -> Option<String> {
+	let captures = regex.captures(body)?;
+	let matched = captures
+		.name("value")
+		.or_else(|| captures.get(1))
+		.or_else(|| captures.get(0))?;
+	Some(matched.as_str().to_string())
+}
+
+/// Text captured from a response for use as a variable in later requests
+pub fn capture(capture: &Capture, headers: &HeaderMap, body: &str) -> Option<String> {
+	match capture {
+		Capture::Header(name) => headers
+			.get(name.trim())
+			.and_then(|value| value.to_str().ok())
+			.map(str::to_string),
+		Capture::Regex(pattern) => regex_capture(body, pattern),
+		Capture::JsonPath(path) => match serde_json::from_str(body) {
+			Ok(json) => capture_json(&json, path),
+			Err(e) => {
+				warn!("Failed to parse HTTP response as JSON for capture: {}", e);
+				None
+			}
+		},
+	}
+}
+
+/// Value at `path` as text: strings as-is, anything else as JSON
+fn capture_json(json: &serde_json::Value, path: &str) -> Option<String> {
+	let path = path.trim();
+	if split_aggregate(path).is_some() {
+		return extract_json_value(json, path).map(|value| value.to_string());
+	}
+
+	let found = if path.starts_with('$') {
+		let query = match JsonPath::parse(path) {
+			Ok(query) => query,
+			Err(e) => {
+				warn!("jsonPath '{}' failed: {}", path, e);
+				return None;
+			}
+		};
+		let nodes = query.query(json).all();
+		match single_match(&nodes) {
+			Ok(node) => node,
+			Err(e) => {
+				warn!("jsonPath '{}' failed: {}", path, e);
+				None
+			}
+		}
+	} else {
+		find_dotted(json, path)
+	};
+
+	found.map(|value| match value {
+		serde_json::Value::String(text) => text.clone(),
+		other => other.to_string(),
+	})
+}
+
+/// One sample line of the Prometheus text exposition format
+#[derive(Debug, PartialEq)]
+struct Sample {
+	name: String,
+	labels: Vec<(String, String)>,
+	value: f64,
+}
+
+/// Parse the samples in `text`, skipping comments and lines that are not samples
+fn parse_samples(text: &str) -> Vec<Sample> {
+	text
+		.lines()
+		.map(str::trim)
+		.filter(|line| !line.is_empty() && !line.starts_with('#'))
+		.filter_map(|line| parse_sample(line).ok())
+		.collect()
+}
+
+fn parse_sample(line: &str) -> Result<Sample, String> {
+	let (name, labels, rest) = parse_series(line)?;
+	let labels = labels
+		.into_iter()
+		.map(|(label, op, value)| match op {
+			"=" => Ok((label, value)),
+			_ => Err(format!("Unexpected '{}' in sample labels", op)),
+		})
+		.collect::<Result<_, _>>()?;
+	// A timestamp may follow the value
+	let value = rest
+		.split_whitespace()
+		.next()
+		.ok_or("Sample has no value")?;
+	let value = value
+		.parse()
+		.map_err(|_| format!("Invalid sample value '{}'", value))?;
+	Ok(Sample {
+		name,
+		labels,
+		value,
+	})
+}
+
+/// Label matcher from a selector: `name op "value"`
+type Matcher = (String, &'static str, String);
+
+/// Split `name{label op "value", ...} rest` into its parts
+fn parse_series(input: &str) -> Result<(String, Vec<Matcher>, &str), String> {
+	let end = input
+		.find(|c: char| c == '{' || c.is_whitespace())
+		.unwrap_or(input.len());
+	let name = input[..end].to_string();
+	if name.is_empty() {
+		return Err("Missing metric name".to_string());
+	}
+
+	let mut rest = input[end..].trim_start();
+	let mut matchers = Vec::new();
+	if let Some(inner) = rest.strip_prefix('{') {
+		rest = inner;
+		loop {
+			rest = rest.trim_start_matches(|c: char| c == ',' || c.is_whitespace());
+			if let Some(after) = rest.strip_prefix('}') {
+				rest = after;
+				break;
+			}
+
+			let label_end = rest
+				.find(|c: char| !(c.is_alphanumeric() || c == '_'))
+				.ok_or("Unterminated label list")?;
+			let label = rest[..label_end].to_string();
+			rest = rest[label_end..].trim_start();
+
+			let op = ["=~", "!~", "!=", "="]
+				.into_iter()
+				.find(|op| rest.starts_with(op))
+				.ok_or_else(|| format!("Expected an operator after label '{}'", label))?;
+			rest = rest[op.len()..].trim_start();
+
+			let (value, after) = parse_quoted(rest)?;
+			matchers.push((label, op, value));
+			rest = after;
+		}
+	}
+
+	Ok((name, matchers, rest))
+}
+
+/// Parse a double-quoted label value with `\\`, `\"` and `\n` escapes
+fn parse_quoted(input: &str) -> Result<(String, &str), String> {
+	let mut chars = input
+		.strip_prefix('"')
+		.ok_or("Expected a quoted label value")?
+		.char_indices();
+	let mut value = String::new();
+	while let Some((i, c)) = chars.next() {
+		match c {
+			'"' => return Ok((value, &input[i + 2..])),
+			'\\' => match chars.next() {
+				Some((_, 'n')) => value.push('\n'),
+				Some((_, escaped)) => value.push(escaped),
+				None => break,
+			},
+			c => value.push(c),
+		}
+	}
+	Err("Unterminated label value".to_string())
+}
+
+/// Label matcher in a selector; regex matchers are anchored and compiled up front
+#[derive(Debug, Clone)]
+struct LabelMatcher {
+	label: String,
+	op: &'static str,
+	value: String,
+	regex: Option<Regex>,
+}
+
+/// Prometheus metric selector such as `sum(http_requests_total{code=~"5.."})`, parsed when the
+/// config is loaded
+#[derive(Debug, Clone)]
+pub struct MetricSelector {
+	text: String,
+	function: Option<&'static str>,
+	name: String,
+	matchers: Vec<LabelMatcher>,
+}
+
+impl FromStr for MetricSelector {
+	type Err = String;
+
+	fn from_str(text: &str) -> Result<Self, String> {
+		let selector = text.trim();
+		let (function, selector) = match split_aggregate(selector) {
+			Some((name, inner)) if name != "length" => {
+				let function = AGGREGATES.into_iter().find(|f| *f == name);
+				(function, inner)
+			}
+			_ => (None, selector),
+		};
+
+		let (name, matchers, rest) = parse_series(selector)?;
+		if !rest.trim().is_empty() {
+			return Err(format!("Unexpected '{}' after selector", rest.trim()));
+		}
+		let matchers = matchers
+			.into_iter()
+			.map(|(label, op, value)| {
+				let regex = match op {
+					"=~" | "!~" => Some(
+						Regex::new(&format!("^(?:{})$", value))
+							.map_err(|e| format!("Invalid label regex '{}': {}", value, e))?,
+					),
+					_ => None,
+				};
+				Ok(LabelMatcher {
+					label,
+					op,
+					value,
+					regex,
+				})
+			})
+			.collect::<Result<_, String>>()?;
+
+		Ok(MetricSelector {
+			text: text.to_string(),
+			function,
+			name,
+			matchers,
+		})
+	}
+}
+
+impl Display for MetricSelector {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(&self.text)
+	}
+}
+
+impl PartialEq for MetricSelector {
+	fn eq(&self, other: &Self) -> bool {
+		self.text == other.text
+	}
+}
+
+impl Serialize for MetricSelector {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(&self.text)
+	}
+}
+
+impl<'de> Deserialize<'de> for MetricSelector {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let text = String::deserialize(deserializer)?;
+		text.parse().map_err(|e| {
+			serde::de::Error::custom(format!(
+				"Invalid prometheusMetrics selector '{}': {}",
+				text, e
+			))
+		})
+	}
+}
+
+impl JsonSchema for MetricSelector {
+	fn inline_schema() -> bool {
+		true
+	}
+
+	fn schema_name() -> Cow<'static, str> {
+		"MetricSelector".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator) -> Schema {
+		json_schema!({ "type": "string" })
+	}
+}
+
+/// Value of the samples matching `selector`. Several matches need an aggregate such as `sum(...)`.
+fn select_samples(samples: &[Sample], selector: &MetricSelector) -> Result<Option<f64>, String> {
+	let values: Vec<f64> = samples
+		.iter()
+		.filter(|sample| sample.name == selector.name)
+		.filter(|sample| {
+			selector.matchers.iter().all(|matcher| {
+				let actual = sample
+					.labels
+					.iter()
+					.find(|(name, _)| *name == matcher.label)
+					.map_or("", |(_, value)| value.as_str());
+				match (matcher.op, &matcher.regex) {
+					("=", _) => actual == matcher.value,
+					("!=", _) => actual != matcher.value,
+					("=~", Some(regex)) => regex.is_match(actual),
+					(_, Some(regex)) => !regex.is_match(actual),
+					_ => false,
+				}
+			})
+		})
+		.map(|sample| sample.value)
+		.collect();
+
+	match selector.function {
+		Some(name) => Ok(aggregate(name, values)),
+		None => match values.as_slice() {
+			[value] => Ok(Some(*value)),
+			[] => Ok(None),
+			many => Err(format!(
+				"matched {} samples; add label matchers or wrap it in sum(), avg(), min(), max() or count()",
+				many.len()
+			)),
+		},
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::Pattern;
+	use serde_json::json;
+
+	#[test]
+	fn test_json_path_expressions() {
+		let json: serde_json::Value = serde_json::from_str(
+			r#"{
+				"services": [
+					{"name": "api", "latency": 12.5, "up": true},
+					{"name": "db", "latency": 40, "up": false},
+					{"name": "cache", "latency": "7.5", "up": true}
+				],
+				"tags": {"a": 1, "b": 2},
+				"metrics": {"cpu.load": 0.75}
+			}"#,
+		)
+		.unwrap();
+
+		let path = "$.services[?(@.name=='db')].latency";
+		assert_eq!(extract_json_value(&json, path), Some(40.0));
+		assert_eq!(
+			extract_json_value(&json, "$.services[0].latency"),
+			Some(12.5)
+		);
+		assert_eq!(
+			extract_json_value(&json, "$.metrics['cpu.load']"),
+			Some(0.75)
+		);
+
+		assert_eq!(
+			extract_json_value(&json, "sum($.services[*].latency)"),
+			Some(60.0)
+		);
+		assert_eq!(
+			extract_json_value(&json, "avg($.services[*].latency)"),
+			Some(20.0)
+		);
+		assert_eq!(
+			extract_json_value(&json, "min($.services[*].latency)"),
+			Some(7.5)
+		);
+		assert_eq!(extract_json_value(&json, "max($..latency)"), Some(40.0));
+		assert_eq!(
+			extract_json_value(&json, "count($.services[?@.up == true])"),
+			Some(2.0)
+		);
+		assert_eq!(extract_json_value(&json, "length($.services)"), Some(3.0));
+		assert_eq!(extract_json_value(&json, "length($.tags)"), Some(2.0));
+
+		// Several matches need an aggregate; no matches and invalid paths yield nothing
+		assert_eq!(extract_json_value(&json, "$.services[*].latency"), None);
+		assert_eq!(extract_json_value(&json, "$.missing"), None);
+		assert_eq!(extract_json_value(&json, "sum($.missing[*])"), None);
+		assert_eq!(extract_json_value(&json, "$.services[?(@.name=="), None);
+	}
+
+	#[test]
+	fn test_simple_object_path() {
+		let json = json!({"cryptocurrencies": {"BTC": 32.543, "ETH": 32.432}});
+		assert_eq!(
+			extract_json_value(&json, "cryptocurrencies.BTC"),
+			Some(32.543)
+		);
+		assert_eq!(
+			extract_json_value(&json, "cryptocurrencies.ETH"),
+			Some(32.432)
+		);
+	}
+
+	#[test]
+	fn test_array_index_path() {
+		let json = json!({
+			"system": {
+				"cpu": [
+					{"percentage": 85.5},
+					{"percentage": 12.3}
+				]
+			}
+		});
+		assert_eq!(
+			extract_json_value(&json, "system.cpu.[0].percentage"),
+			Some(85.5)
+		);
+		assert_eq!(
+			extract_json_value(&json, "system.cpu.[1].percentage"),
+			Some(12.3)
+		);
+	}
+
+	#[test]
+	fn test_top_level_array() {
+		let json = json!([10.0, 20.0, 30.0]);
+		assert_eq!(extract_json_value(&json, "[0]"), Some(10.0));
+		assert_eq!(extract_json_value(&json, "[2]"), Some(30.0));
+	}
+
+	#[test]
+	fn test_nested_path() {
+		let json = json!({"a": {"b": {"c": {"d": 42.0}}}});
+		assert_eq!(extract_json_value(&json, "a.b.c.d"), Some(42.0));
+	}
+
+	#[test]
+	fn test_string_numeric_value() {
+		let json = json!({"value": "123.456"});
+		assert_eq!(extract_json_value(&json, "value"), Some(123.456));
+	}
+
+	#[test]
+	fn test_non_numeric_string_returns_none() {
+		let json = json!({"value": "hello"});
+		assert_eq!(extract_json_value(&json, "value"), None);
+	}
+
+	#[test]
+	fn test_missing_path_returns_none() {
+		let json = json!({"a": {"b": 1}});
+		assert_eq!(extract_json_value(&json, "a.c"), None);
+		assert_eq!(extract_json_value(&json, "x.y.z"), None);
+	}
+
+	#[test]
+	fn test_array_out_of_bounds_returns_none() {
+		let json = json!({"items": [1, 2, 3]});
+		assert_eq!(extract_json_value(&json, "items.[99]"), None);
+	}
+
+	#[test]
+	fn test_integer_value() {
+		let json = json!({"count": 42});
+		assert_eq!(extract_json_value(&json, "count"), Some(42.0));
+	}
+
+	#[test]
+	fn test_boolean_value() {
+		let json = json!({"active": true});
+		assert_eq!(extract_json_value(&json, "active"), Some(1.0));
+	}
+
+	#[test]
+	fn test_null_returns_none() {
+		let json = json!({"value": null});
+		assert_eq!(extract_json_value(&json, "value"), None);
+	}
+
+	#[test]
+	fn test_regex_value() {
+		let body = "status=ok\nqueue_depth=42\nload: 0.75 (5m)\n";
+		let extract = |pattern| extract_regex_value(body, &Regex::new(pattern).unwrap());
+		assert_eq!(extract(r"queue_depth=(\d+)"), Some(42.0));
+		assert_eq!(extract(r"(load): (?P<value>[\d.]+)"), Some(0.75));
+		assert_eq!(extract(r"\d+\.\d+"), Some(0.75));
+		assert_eq!(extract(r"status=(\w+)"), None);
+		assert_eq!(extract(r"missing=(\d+)"), None);
+	}
+
+	#[test]
+	fn test_prometheus_selectors() {
+		let text = r#"
+# HELP http_requests_total Requests served.
+# TYPE http_requests_total counter
+http_requests_total{method="get",code="200"} 1027 1395066363000
+http_requests_total{method="post",code="200"} 3
+http_requests_total{method="get",code="503"} 5
+node_load1 0.42
+node_filesystem_avail_bytes{mountpoint="/",label="a \"quoted\", value"} 1.5e9
+"#;
+		let samples = parse_samples(text);
+		assert_eq!(samples.len(), 5);
+		assert_eq!(samples[4].labels[1].1, r#"a "quoted", value"#);
+
+		let select = |selector: &str| select_samples(&samples, &selector.parse().unwrap());
+		assert_eq!(select("node_load1"), Ok(Some(0.42)));
+		assert_eq!(
+			select(r#"http_requests_total{method="get", code="200"}"#),
+			Ok(Some(1027.0))
+		);
+		assert_eq!(
+			select(r#"sum(http_requests_total{code="200"})"#),
+			Ok(Some(1030.0))
+		);
+		assert_eq!(
+			select(r#"sum(http_requests_total{code=~"5.."})"#),
+			Ok(Some(5.0))
+		);
+		assert_eq!(
+			select(r#"count(http_requests_total{method!="get"})"#),
+			Ok(Some(1.0))
+		);
+		assert_eq!(
+			select(r#"node_filesystem_avail_bytes{mountpoint="/"}"#),
+			Ok(Some(1.5e9))
+		);
+		assert_eq!(select("node_load5"), Ok(None));
+		assert!(
+			select("http_requests_total")
+				.unwrap_err()
+				.contains("matched 3 samples")
+		);
+
+		for invalid in [r#"node_load1{mode="#, r#"up{job=~"("}"#] {
+			assert!(invalid.parse::<MetricSelector>().is_err(), "{}", invalid);
+		}
+	}
+
+	#[test]
+	fn test_capture() {
+		let body = r#"{"auth": {"token": "abc.def"}, "user": {"id": 42}, "csrf": "x"}"#;
+		let mut headers = HeaderMap::new();
+		headers.insert("x-request-id", "req-7".parse().unwrap());
+
+		let captured = |source: Capture| capture(&source, &headers, body);
+		assert_eq!(
+			captured(Capture::JsonPath("auth.token".into())),
+			Some("abc.def".into())
+		);
+		assert_eq!(
+			captured(Capture::JsonPath("$.user.id".into())),
+			Some("42".into())
+		);
+		assert_eq!(
+			captured(Capture::JsonPath("$.user".into())),
+			Some(r#"{"id":42}"#.into())
+		);
+		assert_eq!(
+			captured(Capture::Header("X-Request-Id".into())),
+			Some("req-7".into())
+		);
+		assert_eq!(
+			captured(Capture::Regex(
+				Pattern::new(r#""token": "([^"]+)""#).unwrap()
+			)),
+			Some("abc.def".into())
+		);
+		assert_eq!(captured(Capture::JsonPath("$.missing".into())), None);
+		assert_eq!(captured(Capture::Header("Set-Cookie".into())), None);
+	}
+
+	#[test]
+	fn test_extract_headers() {
+		let http: HttpConfig = serde_json::from_value(json!({
+			"method": "GET",
+			"url": "http://localhost/",
+			"headerMetrics": {
+				"queueDepth": "X-Queue-Depth",
+				"remaining": "x-ratelimit-remaining",
+				"server": "Server",
+				"missing": "X-Missing"
+			}
+		}))
+		.unwrap();
+		let mut headers = HeaderMap::new();
+		headers.insert("x-queue-depth", "17".parse().unwrap());
+		headers.insert("x-ratelimit-remaining", " 4999.5 ".parse().unwrap());
+		headers.insert("server", "nginx".parse().unwrap());
+
+		let mut result = CheckResult::new();
+		extract_headers(&http, &headers, &mut result);
+		assert_eq!(result.get("queueDepth"), Some(17.0));
+		assert_eq!(result.get("remaining"), Some(4999.5));
+		assert_eq!(result.get("server"), None);
+		assert_eq!(result.get("missing"), None);
+	}
+
+	#[test]
+	fn test_extract_body_xml() {
+		let http: HttpConfig = serde_json::from_value(json!({
+			"method": "GET",
+			"url": "http://localhost/status",
+			"xpathPaths": {
+				"active": "/status/connections/@active",
+				"queues": "count(//queue)",
+				"depth": "sum(//queue/depth)",
+				"missing": "/status/nothing"
+			}
+		}))
+		.unwrap();
+		let body = r#"<?xml version="1.0"?>
+<status>
+	<connections active="12"/>
+	<queue><depth>3</depth></queue>
+	<queue><depth>4</depth></queue>
+</status>"#;
+
+		assert!(wants_body(&http));
+		let mut result = CheckResult::new();
+		extract_body(&http, body, &mut result);
+		assert_eq!(result.get("active"), Some(12.0));
+		assert_eq!(result.get("queues"), Some(2.0));
+		assert_eq!(result.get("depth"), Some(7.0));
+		assert_eq!(result.get("missing"), None);
+	}
+}
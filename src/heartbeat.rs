@@ -1,10 +1,14 @@
+use crate::proxy::ProxyClients;
 use crate::pulse_queue::PulseQueueConfig;
+use crate::tls::server_tls_config;
 use crate::utils::{
-	CheckResult, HeartbeatConfig, Monitor, PushMessage, resolve_custom_placeholders,
+	CheckResult, HTTP_METHODS, HeartbeatConfig, Monitor, PushMessage, has_header,
+	resolve_custom_placeholders,
 };
 use crate::ws_client::PulseSender;
 use chrono::{DateTime, SecondsFormat, Utc};
-use reqwest::Client;
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, ClientBuilder, Method};
 use std::error::Error;
 use std::sync::{Arc, OnceLock};
 use std::time::Duration;
@@ -20,19 +24,44 @@ fn build_heartbeat_url(server_url: &str, token: &str) -> String {
 	)
 }
 
+fn client_builder() -> ClientBuilder {
+	Client::builder()
+		.pool_max_idle_per_host(2)
+		.pool_idle_timeout(Duration::from_secs(15))
+		.hickory_dns(true)
+		.timeout(Duration::from_secs(10))
+}
+
 fn http_client() -> &'static Client {
 	static CLIENT: OnceLock<Client> = OnceLock::new();
 	CLIENT.get_or_init(|| {
-		Client::builder()
-			.pool_max_idle_per_host(2)
-			.pool_idle_timeout(Duration::from_secs(15))
-			.hickory_dns(true)
-			.timeout(Duration::from_secs(10))
+		client_builder()
 			.build()
 			.expect("Failed to build HTTP client")
 	})
 }
 
+/// Clients for heartbeats with an explicit `proxy`
+fn proxied_clients() -> &'static ProxyClients {
+	static CLIENTS: OnceLock<ProxyClients> = OnceLock::new();
+	CLIENTS.get_or_init(|| ProxyClients::new(client_builder))
+}
+
+/// Client for the HTTP fallback to the server, using the same TLS settings as the WebSocket
+fn server_http_client() -> &'static Client {
+	static CLIENT: OnceLock<Client> = OnceLock::new();
+	CLIENT.get_or_init(|| {
+		let mut builder = client_builder();
+
+		// Validated at startup, so an error here means the settings were never used
+		if let Ok(Some(tls)) = server_tls_config() {
+			builder = builder.tls_backend_preconfigured((*tls).clone());
+		}
+
+		builder.build().expect("Failed to build HTTP client")
+	})
+}
+
 fn apply_templates(
 	template: &str,
 	latency_str: &str,
@@ -64,7 +93,10 @@ pub async fn send_heartbeat_with_config(
 	latency_ms: f64,
 	custom_placeholders: &[(String, String)],
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-	let client = http_client();
+	let client = match heartbeat.proxy.as_deref() {
+		Some(proxy) => proxied_clients().get(proxy)?,
+		None => http_client().clone(),
+	};
 
 	let latency_str = latency_ms.to_string();
 	let start_time_unix = start_check_time.timestamp_millis().to_string();
@@ -82,12 +114,29 @@ pub async fn send_heartbeat_with_config(
 		custom_placeholders,
 	);
 
-	let mut request = match heartbeat.method.to_uppercase().as_str() {
-		"GET" => client.get(&url),
-		"POST" => client.post(&url),
-		"HEAD" => client.head(&url),
-		_ => return Err(format!("Unsupported HTTP method: {}", heartbeat.method).into()),
-	};
+	let method = heartbeat.method.to_uppercase();
+	if !HTTP_METHODS.contains(&method.as_str()) {
+		return Err(format!("Unsupported HTTP method: {}", heartbeat.method).into());
+	}
+	let mut request = client.request(Method::from_bytes(method.as_bytes())?, &url);
+
+	if let Some(body) = &heartbeat.body {
+		let (content_type, body) = body.render(|text| {
+			apply_templates(
+				text,
+				&latency_str,
+				&start_time_iso,
+				&end_time_iso,
+				&start_time_unix,
+				&end_time_unix,
+				custom_placeholders,
+			)
+		});
+		if !has_header(heartbeat.headers.as_ref(), "content-type") {
+			request = request.header(CONTENT_TYPE, content_type);
+		}
+		request = request.body(body);
+	}
 
 	if let Some(headers) = &heartbeat.headers {
 		for header in headers {
@@ -116,6 +165,7 @@ pub async fn send_heartbeat_with_config(
 }
 
 /// Send heartbeat using token and server_url via HTTP (fallback for WebSocket mode)
+#[allow(clippy::too_many_arguments)]
 pub async fn send_heartbeat_with_token_http(
 	server_url: &str,
 	token: &str,
@@ -126,7 +176,7 @@ pub async fn send_heartbeat_with_token_http(
 	max_retries: u32,
 	retry_delay_ms: u64,
 ) -> Result<(), Box<dyn Error + Send + Sync>> {
-	let client = http_client();
+	let client = server_http_client();
 
 	let latency_str = latency_ms.to_string();
 	let start_time_unix = start_check_time.timestamp_millis().to_string();
@@ -179,6 +229,7 @@ pub async fn send_heartbeat_with_token_http(
 /// Send heartbeat using WebSocket connection
 pub async fn send_heartbeat_via_websocket(
 	pulse_sender: &Arc<RwLock<Option<PulseSender>>>,
+	monitor: &Monitor,
 	token: &str,
 	start_check_time: DateTime<Utc>,
 	end_check_time: DateTime<Utc>,
@@ -194,7 +245,7 @@ pub async fn send_heartbeat_via_websocket(
 		Some(start_time_iso),
 		Some(end_time_iso),
 	)
-	.with_custom_metrics(check_result);
+	.with_custom_metrics(monitor, check_result);
 
 	// Get the sender and send the message
 	let sender_guard = pulse_sender.read().await;
@@ -246,6 +297,7 @@ pub async fn send_heartbeat(
 	if let (Some(token), Some(pulse_tx)) = (&monitor.token, pulse_sender) {
 		match send_heartbeat_via_websocket(
 			pulse_tx,
+			monitor,
 			token,
 			start_check_time,
 			end_check_time,
@@ -0,0 +1,922 @@
+use std::collections::{BTreeMap, HashMap};
+use std::error::Error;
+use std::net::IpAddr;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use cookie_store::{CookieStore, RawCookie};
+use hickory_resolver::TokioResolver;
+use hickory_resolver::config::LookupIpStrategy;
+use http::header::{self, HeaderMap, HeaderValue};
+use http::{Method, StatusCode, Version};
+use http_body_util::{BodyExt, Full};
+use hyper::body::Incoming;
+use hyper::client::conn::{TrySendError, http1, http2};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use rustls::ClientConfig;
+use rustls::pki_types::ServerName;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+use tokio::task::JoinSet;
+use tokio_rustls::TlsConnector;
+use url::{Position, Url};
+
+use crate::proxy::{self, Destination, Intercept};
+use crate::tls::{self, CheckTls, FileStamp, PeerCertificate, TlsSettings};
+use crate::utils::{CheckResult, HttpVersion};
+
+/// Redirects followed before giving up, matching reqwest's default
+pub const MAX_REDIRECTS: usize = 10;
+
+/// Largest response body kept in memory for extraction and assertions
+pub const MAX_BODY_BYTES: usize = 10 * 1024 * 1024;
+
+/// Idle pooled connections are closed after this long, matching reqwest's default
+const POOL_IDLE: Duration = Duration::from_secs(90);
+
+/// Head start each address gets before the next one is tried, close to reqwest's 300 ms
+const CONNECT_ATTEMPT_DELAY: Duration = Duration::from_millis(250);
+
+/// Headers dropped when a redirect leaves the original host
+const SENSITIVE_HEADERS: [header::HeaderName; 4] = [
+	header::AUTHORIZATION,
+	header::COOKIE,
+	header::PROXY_AUTHORIZATION,
+	header::WWW_AUTHENTICATE,
+];
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Time spent in each phase of a request, in milliseconds
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Timings {
+	pub dns_ms: f64,
+	pub connect_ms: f64,
+	pub tls_ms: f64,
+	/// From sending the request to receiving the response head
+	pub ttfb_ms: f64,
+	pub download_ms: f64,
+}
+
+impl Timings {
+	fn add(&mut self, other: &Timings) {
+		self.dns_ms += other.dns_ms;
+		self.connect_ms += other.connect_ms;
+		self.tls_ms += other.tls_ms;
+		self.ttfb_ms += other.ttfb_ms;
+		self.download_ms += other.download_ms;
+	}
+
+	/// Store the phases as `dnsMs`, `connectMs`, `tlsMs`, `ttfbMs` and `downloadMs`
+	pub fn record(&self, result: &mut CheckResult) {
+		result.set("dnsMs", self.dns_ms);
+		result.set("connectMs", self.connect_ms);
+		result.set("tlsMs", self.tls_ms);
+		result.set("ttfbMs", self.ttfb_ms);
+		result.set("downloadMs", self.download_ms);
+	}
+}
+
+fn elapsed_ms(start: Instant) -> f64 {
+	start.elapsed().as_secs_f64() * 1000.0
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+	pub method: Method,
+	pub url: Url,
+	pub headers: HeaderMap,
+	pub body: Option<Bytes>,
+	/// Keep the response body (up to [`MAX_BODY_BYTES`]); otherwise it is only counted
+	pub keep_body: bool,
+}
+
+#[derive(Debug)]
+pub struct Response {
+	pub status: StatusCode,
+	pub headers: HeaderMap,
+	/// Empty unless the request set `keep_body`
+	pub body: Bytes,
+	/// Size of the body, whether or not it was kept
+	pub body_bytes: usize,
+	/// Phase timings summed over every hop of a redirect chain
+	pub timings: Timings,
+	/// Certificate presented by the final hop, for HTTPS
+	pub peer: Option<PeerCertificate>,
+	/// Protocol the final hop was served over
+	pub version: Version,
+}
+
+/// Transport options for a check client; clients are cached per distinct set
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ClientOptions {
+	/// Per-monitor proxy; otherwise the environment applies
+	pub proxy: Option<String>,
+	pub tls: TlsSettings,
+	pub follow_redirects: bool,
+	pub max_redirects: usize,
+	pub version: HttpVersion,
+	/// `host:port` or `host` -> address to connect to instead of resolving the name
+	pub resolve: BTreeMap<String, IpAddr>,
+}
+
+impl Default for ClientOptions {
+	fn default() -> Self {
+		ClientOptions {
+			proxy: None,
+			tls: TlsSettings::default(),
+			follow_redirects: true,
+			max_redirects: MAX_REDIRECTS,
+			version: HttpVersion::default(),
+			resolve: BTreeMap::new(),
+		}
+	}
+}
+
+enum Sender {
+	Http1(http1::SendRequest<Full<Bytes>>),
+	Http2(http2::SendRequest<Full<Bytes>>),
+}
+
+type SendResult = Result<http::Response<Incoming>, TrySendError<http::Request<Full<Bytes>>>>;
+
+impl Sender {
+	fn is_http2(&self) -> bool {
+		matches!(self, Sender::Http2(_))
+	}
+
+	fn is_closed(&self) -> bool {
+		match self {
+			Sender::Http1(sender) => sender.is_closed(),
+			Sender::Http2(sender) => sender.is_closed(),
+		}
+	}
+
+	async fn ready(&mut self) -> hyper::Result<()> {
+		match self {
+			Sender::Http1(sender) => sender.ready().await,
+			Sender::Http2(sender) => sender.ready().await,
+		}
+	}
+
+	async fn send(&mut self, request: http::Request<Full<Bytes>>) -> SendResult {
+		match self {
+			Sender::Http1(sender) => sender.try_send_request(request).await,
+			Sender::Http2(sender) => sender.try_send_request(request).await,
+		}
+	}
+}
+
+/// An established connection, returned to the pool once its response has been read
+struct Connection {
+	sender: Sender,
+	/// Certificate presented when the connection was made, for HTTPS
+	peer: Option<PeerCertificate>,
+}
+
+/// Scheme (`true` for HTTPS), host and port a pooled connection leads to
+type PoolKey = (bool, String, u16);
+
+/// Idle connections per destination, with the time each was returned
+type Pool = Mutex<HashMap<PoolKey, Vec<(Instant, Connection)>>>;
+
+/// HTTP/1.1 and HTTP/2 client for checks that times every phase of the request.
+///
+/// Connections are kept alive and reused for up to [`POOL_IDLE`]; a request on a reused
+/// connection reports 0 for DNS, connect and TLS, as no such phase took place.
+#[derive(Clone)]
+pub struct HttpClient {
+	tls: Arc<CheckTls>,
+	/// `tls.config` with the ALPN protocols for `version`
+	tls_config: Arc<ClientConfig>,
+	proxy: Option<String>,
+	cookies: Option<Arc<Mutex<CookieStore>>>,
+	follow_redirects: bool,
+	max_redirects: usize,
+	version: HttpVersion,
+	resolve: Arc<BTreeMap<String, IpAddr>>,
+	pool: Arc<Pool>,
+}
+
+impl HttpClient {
+	/// Client for `options`; a `proxy` there is a per-monitor proxy, otherwise the environment applies
+	pub fn with_options(options: &ClientOptions) -> Result<Self, String> {
+		let tls = tls::check_tls(&options.tls)?;
+		let mut tls_config = (*tls.config).clone();
+		tls_config.alpn_protocols = match options.version {
+			HttpVersion::Http1 => Vec::new(),
+			HttpVersion::Http2 => vec![b"h2".to_vec(), b"http/1.1".to_vec()],
+			HttpVersion::PriorKnowledge => vec![b"h2".to_vec()],
+		};
+
+		Ok(HttpClient {
+			tls,
+			tls_config: Arc::new(tls_config),
+			proxy: options.proxy.clone(),
+			cookies: None,
+			follow_redirects: options.follow_redirects,
+			max_redirects: options.max_redirects,
+			version: options.version,
+			resolve: Arc::new(options.resolve.clone()),
+			pool: Arc::default(),
+		})
+	}
+
+	/// Client for `options`, built once per distinct set of options and certificate files.
+	///
+	/// Monitors with the same options share the setup (roots, client certificate, ALPN) and the
+	/// connection pool. A rotated `caCert` or `clientCert` gets a new client, and entries unused
+	/// for [`tls::CACHE_IDLE`] are dropped so options removed by a reload don't accumulate.
+	pub fn cached(options: &ClientOptions) -> Result<Self, String> {
+		type Cache = HashMap<(ClientOptions, FileStamp), (Instant, HttpClient)>;
+		static CLIENTS: OnceLock<Mutex<Cache>> = OnceLock::new();
+		let mut clients = CLIENTS
+			.get_or_init(Default::default)
+			.lock()
+			.unwrap_or_else(|e| e.into_inner());
+		let key = (options.clone(), options.tls.stamp());
+		if let Some((used, client)) = clients.get_mut(&key) {
+			*used = Instant::now();
+			return Ok(client.clone());
+		}
+
+		let client = Self::with_options(options)?;
+		clients.retain(|_, (used, _)| used.elapsed() < tls::CACHE_IDLE);
+		clients.insert(key, (Instant::now(), client.clone()));
+		Ok(client)
+	}
+
+	/// Send and store cookies through `jar`, including on every redirect hop
+	pub fn with_cookies(mut self, jar: Arc<Mutex<CookieStore>>) -> Self {
+		self.cookies = Some(jar);
+		self
+	}
+
+	/// Send the request, following redirects the way reqwest does.
+	pub async fn send(&self, mut request: Request) -> Result<Response, BoxError> {
+		let mut timings = Timings::default();
+		// A `Cookie` header set by the caller is sent alongside the jar's cookies
+		let mut explicit_cookie = request.headers.remove(header::COOKIE);
+
+		for _ in 0..=self.max_redirects {
+			if let Some(jar) = &self.cookies {
+				add_cookies(&mut request, explicit_cookie.as_ref(), jar)?;
+			} else if let Some(cookie) = &explicit_cookie {
+				request.headers.insert(header::COOKIE, cookie.clone());
+			}
+
+			let mut response = self.send_once(&request).await?;
+			timings.add(&response.timings);
+			if let Some(jar) = &self.cookies {
+				store_cookies(&response.headers, &request.url, jar);
+			}
+
+			let location = response
+				.headers
+				.get(header::LOCATION)
+				.and_then(|value| value.to_str().ok());
+			let Some(location) =
+				location.filter(|_| self.follow_redirects && response.status.is_redirection())
+			else {
+				response.timings = timings;
+				return Ok(response);
+			};
+
+			let next = request
+				.url
+				.join(location)
+				.map_err(|e| format!("Invalid redirect location '{}': {}", location, e))?;
+			redirect(&mut request, response.status, next);
+			if !request.headers.contains_key(header::COOKIE) {
+				// Dropped because the redirect left the original host
+				explicit_cookie = None;
+			}
+		}
+
+		Err(format!("Too many redirects (more than {})", self.max_redirects).into())
+	}
+
+	async fn send_once(&self, request: &Request) -> Result<Response, BoxError> {
+		let url = &request.url;
+		let https = match url.scheme() {
+			"https" => true,
+			"http" => false,
+			other => return Err(format!("Unsupported URL scheme: {}", other).into()),
+		};
+		let host = match url.host() {
+			Some(url::Host::Ipv6(ip)) => ip.to_string(),
+			Some(host) => host.to_string(),
+			None => return Err("URL has no host".into()),
+		};
+		let port = url.port_or_known_default().unwrap_or(80);
+
+		let destination = if https {
+			Destination::Https
+		} else {
+			Destination::Http
+		};
+		let route = proxy::resolve(self.proxy.as_deref(), destination, &host)?;
+		// Plain HTTP through an HTTP proxy is forwarded; everything else is tunnelled
+		let forward = !https
+			&& route
+				.as_ref()
+				.is_some_and(|route| route.uri().scheme_str() == Some("http"));
+		let forward_proxy = forward.then_some(route.as_ref()).flatten();
+		let key = (https, host.clone(), port);
+
+		while let Some(mut connection) = self.checkout(&key).await {
+			let head = build_request(request, forward_proxy, connection.sender.is_http2())?;
+			let start = Instant::now();
+			match connection.sender.send(head).await {
+				Ok(response) => {
+					let timings = Timings {
+						ttfb_ms: elapsed_ms(start),
+						..Default::default()
+					};
+					return self
+						.read_response(key, connection, response, timings, request.keep_body)
+						.await;
+				}
+				// The server closed the idle connection before the request went out, or before
+				// answering a request that may be repeated (RFC 9110 section 9.2.2)
+				Err(e)
+					if e.message().is_some()
+						|| (e.error().is_incomplete_message() && request.method.is_idempotent()) =>
+				{
+					continue;
+				}
+				Err(e) => return Err(e.into_error().into()),
+			}
+		}
+
+		let mut timings = Timings::default();
+		let stream = match &route {
+			None => {
+				let addrs: Vec<SocketAddr> = match self.resolved(&host, port) {
+					Some(ip) => vec![SocketAddr::new(ip, port)],
+					None => {
+						let start = Instant::now();
+						let addrs = resolver()?
+							.lookup_ip(host.as_str())
+							.await?
+							.into_iter()
+							.map(|ip| SocketAddr::new(ip, port))
+							.collect();
+						timings.dns_ms = elapsed_ms(start);
+						addrs
+					}
+				};
+
+				let start = Instant::now();
+				let stream = connect_any(&addrs).await?;
+				timings.connect_ms = elapsed_ms(start);
+				stream
+			}
+			Some(route) => {
+				let start = Instant::now();
+				let stream = if forward {
+					proxy::connect_to_proxy(route).await?
+				} else {
+					proxy::connect(Some(route), &host, port).await?
+				};
+				timings.connect_ms = elapsed_ms(start);
+				stream
+			}
+		};
+		stream.set_nodelay(true)?;
+
+		let mut connection = if https {
+			let start = Instant::now();
+			let server_name = ServerName::try_from(host.clone())?;
+			let stream = TlsConnector::from(self.tls_config.clone())
+				.connect(server_name.clone(), stream)
+				.await?;
+			timings.tls_ms = elapsed_ms(start);
+
+			let tls = stream.get_ref().1;
+			let http2 = match self.version {
+				HttpVersion::Http1 => false,
+				HttpVersion::Http2 => tls.alpn_protocol() == Some(b"h2"),
+				HttpVersion::PriorKnowledge => true,
+			};
+			let chain = tls.peer_certificates().unwrap_or_default();
+			let peer = self.tls.inspect(chain, &server_name)?;
+			self.tls.verify(&peer)?;
+
+			Connection {
+				sender: handshake(stream, http2).await?,
+				peer: Some(peer),
+			}
+		} else {
+			// Cleartext HTTP/2 (h2c) needs prior knowledge, and forward proxies speak HTTP/1.1
+			let http2 = self.version == HttpVersion::PriorKnowledge && forward_proxy.is_none();
+			Connection {
+				sender: handshake(stream, http2).await?,
+				peer: None,
+			}
+		};
+
+		let head = build_request(request, forward_proxy, connection.sender.is_http2())?;
+		let start = Instant::now();
+		let response = connection
+			.sender
+			.send(head)
+			.await
+			.map_err(TrySendError::into_error)?;
+		timings.ttfb_ms = elapsed_ms(start);
+		self
+			.read_response(key, connection, response, timings, request.keep_body)
+			.await
+	}
+
+	/// Read the whole response, keeping the body only if asked to, then return its connection
+	/// to the pool
+	async fn read_response(
+		&self,
+		key: PoolKey,
+		connection: Connection,
+		response: http::Response<Incoming>,
+		mut timings: Timings,
+		keep_body: bool,
+	) -> Result<Response, BoxError> {
+		let (parts, mut incoming) = response.into_parts();
+		let start = Instant::now();
+		let mut body = Vec::new();
+		let mut body_bytes = 0;
+		while let Some(frame) = incoming.frame().await {
+			let Ok(data) = frame?.into_data() else {
+				continue;
+			};
+			body_bytes += data.len();
+			if keep_body {
+				if body.len() + data.len() > MAX_BODY_BYTES {
+					return Err(
+						format!(
+							"Response body is larger than {} MiB",
+							MAX_BODY_BYTES / (1024 * 1024)
+						)
+						.into(),
+					);
+				}
+				body.extend_from_slice(&data);
+			}
+		}
+		timings.download_ms = elapsed_ms(start);
+
+		let peer = connection.peer.clone();
+		self.checkin(key, connection);
+		Ok(Response {
+			status: parts.status,
+			headers: parts.headers,
+			body: Bytes::from(body),
+			body_bytes,
+			timings,
+			peer,
+			version: parts.version,
+		})
+	}
+
+	/// An idle connection to `key` that is still open, if the pool has one
+	async fn checkout(&self, key: &PoolKey) -> Option<Connection> {
+		loop {
+			let (returned, mut connection) = self
+				.pool
+				.lock()
+				.unwrap_or_else(|e| e.into_inner())
+				.get_mut(key)?
+				.pop()?;
+			if returned.elapsed() < POOL_IDLE && connection.sender.ready().await.is_ok() {
+				return Some(connection);
+			}
+		}
+	}
+
+	/// Keep `connection` for the next request to `key`, dropping expired and closed ones
+	fn checkin(&self, key: PoolKey, connection: Connection) {
+		let mut pool = self.pool.lock().unwrap_or_else(|e| e.into_inner());
+		pool.retain(|_, idle| {
+			idle.retain(|(returned, connection)| {
+				returned.elapsed() < POOL_IDLE && !connection.sender.is_closed()
+			});
+			!idle.is_empty()
+		});
+		pool
+			.entry(key)
+			.or_default()
+			.push((Instant::now(), connection));
+	}
+
+	/// Address from `resolve` for `host:port`, or for `host` on any port
+	fn resolved(&self, host: &str, port: u16) -> Option<IpAddr> {
+		self
+			.resolve
+			.get(&format!("{}:{}", host, port))
+			.or_else(|| self.resolve.get(host))
+			.copied()
+	}
+}
+
+/// Set the `Cookie` header from `explicit` plus whatever `jar` holds for the request URL
+fn add_cookies(
+	request: &mut Request,
+	explicit: Option<&HeaderValue>,
+	jar: &Mutex<CookieStore>,
+) -> Result<(), BoxError> {
+	let jar = jar.lock().unwrap_or_else(|e| e.into_inner());
+	let mut pairs: Vec<String> = explicit
+		.and_then(|value| value.to_str().ok())
+		.map(str::to_string)
+		.into_iter()
+		.collect();
+	pairs.extend(
+		jar
+			.get_request_values(&request.url)
+			.map(|(name, value)| format!("{}={}", name, value)),
+	);
+
+	if pairs.is_empty() {
+		request.headers.remove(header::COOKIE);
+	} else {
+		request
+			.headers
+			.insert(header::COOKIE, HeaderValue::from_str(&pairs.join("; "))?);
+	}
+	Ok(())
+}
+
+fn store_cookies(headers: &HeaderMap, url: &Url, jar: &Mutex<CookieStore>) {
+	let cookies = headers
+		.get_all(header::SET_COOKIE)
+		.iter()
+		.filter_map(|value| value.to_str().ok())
+		.filter_map(|value| RawCookie::parse(value.to_string()).ok());
+	jar
+		.lock()
+		.unwrap_or_else(|e| e.into_inner())
+		.store_response_cookies(cookies, url);
+}
+
+/// DNS resolver for checks, configured like reqwest's `hickory-dns` resolver: the system's
+/// name servers, asking for both IPv4 and IPv6 addresses
+fn resolver() -> Result<&'static TokioResolver, String> {
+	static RESOLVER: OnceLock<Result<TokioResolver, String>> = OnceLock::new();
+	RESOLVER
+		.get_or_init(|| {
+			let mut builder = TokioResolver::builder_tokio()
+				.map_err(|e| format!("Failed to read the system DNS config: {}", e))?;
+			builder.options_mut().ip_strategy = LookupIpStrategy::Ipv4AndIpv6;
+			Ok(builder.build())
+		})
+		.as_ref()
+		.map_err(Clone::clone)
+}
+
+/// Alternate IPv6 and IPv4 addresses, starting with IPv6 (RFC 8305 section 4)
+fn interleave(addrs: &[SocketAddr]) -> Vec<SocketAddr> {
+	let (v6, v4): (Vec<SocketAddr>, Vec<SocketAddr>) = addrs.iter().partition(|a| a.is_ipv6());
+	let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+	let mut ordered = Vec::with_capacity(addrs.len());
+	while ordered.len() < addrs.len() {
+		ordered.extend(v6.next());
+		ordered.extend(v4.next());
+	}
+	ordered
+}
+
+/// Connect to the first address that accepts, racing them Happy Eyeballs style: the next
+/// address is tried when the previous attempt fails or has not connected within
+/// [`CONNECT_ATTEMPT_DELAY`], so one unreachable address does not use up the check timeout.
+async fn connect_any(addrs: &[SocketAddr]) -> std::io::Result<TcpStream> {
+	let mut pending = interleave(addrs).into_iter();
+	let mut attempts = JoinSet::new();
+	let mut last_error = None;
+
+	loop {
+		if let Some(addr) = pending.next() {
+			attempts.spawn(TcpStream::connect(addr));
+		}
+		let finished = if pending.as_slice().is_empty() {
+			attempts.join_next().await
+		} else {
+			match tokio::time::timeout(CONNECT_ATTEMPT_DELAY, attempts.join_next()).await {
+				Ok(finished) => finished,
+				Err(_) => continue,
+			}
+		};
+		// Returning drops the set, which aborts the attempts still in flight
+		match finished {
+			Some(Ok(Ok(stream))) => return Ok(stream),
+			Some(Ok(Err(e))) => last_error = Some(e),
+			Some(Err(e)) => last_error = Some(std::io::Error::other(e)),
+			None => break,
+		}
+	}
+	Err(last_error.unwrap_or_else(|| std::io::Error::other("Host resolved to no addresses")))
+}
+
+/// Rewrite the request for the next hop: 303 (and 301/302 after a POST) switch to a bodiless GET,
+/// and credentials are dropped when the redirect leaves the original host.
+fn redirect(request: &mut Request, status: StatusCode, next: Url) {
+	let to_get = request.method != Method::HEAD
+		&& (status == StatusCode::SEE_OTHER
+			|| (request.method == Method::POST
+				&& matches!(status, StatusCode::MOVED_PERMANENTLY | StatusCode::FOUND)));
+	if to_get {
+		request.method = Method::GET;
+		request.body = None;
+		request.headers.remove(header::CONTENT_TYPE);
+		request.headers.remove(header::CONTENT_LENGTH);
+	}
+
+	if next.host_str() != request.url.host_str()
+		|| next.port_or_known_default() != request.url.port_or_known_default()
+	{
+		for name in &SENSITIVE_HEADERS {
+			request.headers.remove(name);
+		}
+	}
+
+	request.url = next;
+}
+
+fn build_request(
+	request: &Request,
+	forward_proxy: Option<&Intercept>,
+	http2: bool,
+) -> Result<http::Request<Full<Bytes>>, BoxError> {
+	let url = &request.url;
+	// Proxies and HTTP/2 (for :scheme and :authority) get the absolute URL, HTTP/1.1 origin
+	// servers only the path
+	let target = if forward_proxy.is_some() || http2 {
+		url[..Position::AfterQuery].to_string()
+	} else {
+		url[Position::BeforePath..Position::AfterQuery].to_string()
+	};
+
+	let mut builder = http::Request::builder()
+		.method(request.method.clone())
+		.uri(target);
+	let headers = builder.headers_mut().ok_or("Invalid request")?;
+	headers.extend(request.headers.clone());
+
+	if !http2 && !headers.contains_key(header::HOST) {
+		let host = url.host_str().ok_or("URL has no host")?;
+		let value = match url.port() {
+			Some(port) => format!("{}:{}", host, port),
+			None => host.to_string(),
+		};
+		headers.insert(header::HOST, HeaderValue::from_str(&value)?);
+	}
+	// reqwest always sent this, so monitors keep the headers they had
+	headers
+		.entry(header::ACCEPT)
+		.or_insert(HeaderValue::from_static("*/*"));
+	if let Some(auth) = forward_proxy.and_then(|proxy| proxy.basic_auth()) {
+		headers.insert(header::PROXY_AUTHORIZATION, auth.clone());
+	}
+
+	Ok(builder.body(Full::new(request.body.clone().unwrap_or_default()))?)
+}
+
+/// Start HTTP/1.1 or HTTP/2 on an established connection, driving it in the background
+async fn handshake<S>(stream: S, http2: bool) -> Result<Sender, BoxError>
+where
+	S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+	if http2 {
+		let (sender, connection) = http2::handshake(TokioExecutor::new(), TokioIo::new(stream)).await?;
+		tokio::spawn(async move {
+			let _ = connection.await;
+		});
+		Ok(Sender::Http2(sender))
+	} else {
+		let (sender, connection) = http1::handshake(TokioIo::new(stream)).await?;
+		tokio::spawn(async move {
+			let _ = connection.await;
+		});
+		Ok(Sender::Http1(sender))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+	use tokio::net::TcpListener;
+
+	/// Accept `responses.len()` connections, answer each with the next canned response,
+	/// and return the request heads that were received.
+	async fn serve(listener: TcpListener, responses: Vec<String>) -> Vec<String> {
+		let mut requests = Vec::new();
+		for response in responses {
+			let (mut socket, _) = listener.accept().await.unwrap();
+			let mut head = Vec::new();
+			while !head.ends_with(b"\r\n\r\n") {
+				head.push(socket.read_u8().await.unwrap());
+			}
+			let head = String::from_utf8(head).unwrap();
+			if let Some(length) = head
+				.lines()
+				.find_map(|line| line.strip_prefix("content-length: "))
+			{
+				let mut body = vec![0u8; length.parse().unwrap()];
+				socket.read_exact(&mut body).await.unwrap();
+			}
+			socket.write_all(response.as_bytes()).await.unwrap();
+			requests.push(head);
+		}
+		requests
+	}
+
+	fn request(method: Method, url: &str) -> Request {
+		let mut headers = HeaderMap::new();
+		headers.insert(header::AUTHORIZATION, HeaderValue::from_static("Bearer x"));
+		Request {
+			method,
+			url: Url::parse(url).unwrap(),
+			headers,
+			body: Some(Bytes::from_static(b"{}")),
+			keep_body: true,
+		}
+	}
+
+	#[tokio::test]
+	async fn test_send_follows_redirects_with_timings() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let server = tokio::spawn(serve(
+			listener,
+			vec![
+				"HTTP/1.1 303 See Other\r\nLocation: /done?x=1\r\nContent-Length: 0\r\n\r\n".to_string(),
+				"HTTP/1.1 200 OK\r\nContent-Length: 5\r\n\r\nhello".to_string(),
+			],
+		));
+
+		let client = HttpClient::with_options(&ClientOptions {
+			proxy: Some(proxy::DIRECT.to_string()),
+			..Default::default()
+		})
+		.unwrap();
+		let url = format!("http://127.0.0.1:{}/start", addr.port());
+		let response = client.send(request(Method::POST, &url)).await.unwrap();
+
+		assert_eq!(response.status, StatusCode::OK);
+		assert_eq!(&response.body[..], b"hello");
+		assert_eq!(response.timings.tls_ms, 0.0);
+		assert!(response.timings.connect_ms > 0.0);
+
+		let requests = server.await.unwrap();
+		assert!(requests[0].starts_with("POST /start HTTP/1.1\r\n"));
+		assert!(requests[0].contains("accept: */*\r\n"));
+		assert!(requests[1].starts_with("GET /done?x=1 HTTP/1.1\r\n"));
+		assert!(!requests[1].contains("content-length"));
+		// Same host, so credentials are kept
+		assert!(requests[1].contains("authorization: Bearer x"));
+	}
+
+	#[tokio::test]
+	async fn test_resolve_override_without_following_redirects() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let server = tokio::spawn(serve(
+			listener,
+			vec!["HTTP/1.1 302 Found\r\nLocation: /next\r\nContent-Length: 0\r\n\r\n".to_string()],
+		));
+
+		let client = HttpClient::with_options(&ClientOptions {
+			proxy: Some(proxy::DIRECT.to_string()),
+			follow_redirects: false,
+			resolve: BTreeMap::from([(
+				format!("api.example.test:{}", port),
+				"127.0.0.1".parse().unwrap(),
+			)]),
+			..Default::default()
+		})
+		.unwrap();
+		let url = format!("http://api.example.test:{}/start", port);
+		let response = client.send(request(Method::GET, &url)).await.unwrap();
+
+		assert_eq!(response.status, StatusCode::FOUND);
+		assert_eq!(response.timings.dns_ms, 0.0);
+		let requests = server.await.unwrap();
+		assert!(requests[0].contains(&format!("host: api.example.test:{}\r\n", port)));
+	}
+
+	#[tokio::test]
+	async fn test_keep_alive_connection_is_reused() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		// Answer two requests on the first connection only
+		let server = tokio::spawn(async move {
+			let (mut socket, _) = listener.accept().await.unwrap();
+			for _ in 0..2 {
+				let mut head = Vec::new();
+				while !head.ends_with(b"\r\n\r\n") {
+					head.push(socket.read_u8().await.unwrap());
+				}
+				socket
+					.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\nok")
+					.await
+					.unwrap();
+			}
+		});
+
+		let client = HttpClient::with_options(&ClientOptions {
+			proxy: Some(proxy::DIRECT.to_string()),
+			..Default::default()
+		})
+		.unwrap();
+		let url = format!("http://127.0.0.1:{}/", port);
+		let mut get = request(Method::GET, &url);
+		get.body = None;
+
+		let first = client.send(get.clone()).await.unwrap();
+		assert!(first.timings.connect_ms > 0.0);
+		let second = client.send(get).await.unwrap();
+		assert_eq!(&second.body[..], b"ok");
+		assert_eq!(second.timings.connect_ms, 0.0);
+		server.await.unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_body_is_counted_unless_kept() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let port = listener.local_addr().unwrap().port();
+		let large = format!(
+			"HTTP/1.1 200 OK\r\nContent-Length: {}\r\n\r\n{}",
+			MAX_BODY_BYTES + 1,
+			"x".repeat(MAX_BODY_BYTES + 1)
+		);
+		let server = tokio::spawn(serve(listener, vec![large.clone(), large]));
+
+		let client = HttpClient::with_options(&ClientOptions {
+			proxy: Some(proxy::DIRECT.to_string()),
+			..Default::default()
+		})
+		.unwrap();
+		let url = format!("http://127.0.0.1:{}/download", port);
+		let mut get = request(Method::GET, &url);
+		get.body = None;
+		get.keep_body = false;
+
+		let response = client.send(get.clone()).await.unwrap();
+		assert_eq!(response.body_bytes, MAX_BODY_BYTES + 1);
+		assert!(response.body.is_empty());
+
+		get.keep_body = true;
+		let error = client.send(get).await.unwrap_err();
+		assert_eq!(error.to_string(), "Response body is larger than 10 MiB");
+		server.await.unwrap();
+	}
+
+	#[tokio::test]
+	async fn test_connect_moves_past_unresponsive_address() {
+		// Once its accept queue is full a listener drops new SYNs, like a filtered address
+		let socket = tokio::net::TcpSocket::new_v4().unwrap();
+		socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+		let silent = socket.listen(0).unwrap();
+		let silent_addr = silent.local_addr().unwrap();
+		let mut queued = Vec::new();
+		for _ in 0..64 {
+			match tokio::time::timeout(Duration::from_millis(100), TcpStream::connect(silent_addr)).await
+			{
+				Ok(Ok(stream)) => queued.push(stream),
+				_ => break,
+			}
+		}
+
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let addr = listener.local_addr().unwrap();
+		let start = Instant::now();
+		let stream = connect_any(&[silent_addr, addr]).await.unwrap();
+		assert_eq!(stream.peer_addr().unwrap(), addr);
+		assert!(start.elapsed() < Duration::from_secs(1));
+	}
+
+	#[test]
+	fn test_interleave_address_families() {
+		let addrs: Vec<SocketAddr> = ["10.0.0.1:80", "10.0.0.2:80", "[::1]:80", "[::2]:80"]
+			.iter()
+			.map(|a| a.parse().unwrap())
+			.collect();
+		assert_eq!(
+			interleave(&addrs),
+			vec![addrs[2], addrs[0], addrs[3], addrs[1]]
+		);
+	}
+
+	#[test]
+	fn test_redirect_drops_credentials_across_hosts() {
+		let mut req = request(Method::PUT, "https://api.example.com/a");
+		redirect(
+			&mut req,
+			StatusCode::TEMPORARY_REDIRECT,
+			Url::parse("https://login.example.com/b").unwrap(),
+		);
+		assert_eq!(req.method, Method::PUT);
+		assert!(req.body.is_some());
+		assert!(!req.headers.contains_key(header::AUTHORIZATION));
+	}
+}
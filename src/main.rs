@@ -1,18 +1,33 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::SystemTime;
 use std::{fs, str::FromStr};
+use tokio::sync::mpsc;
 use tokio::time::{Duration, sleep};
 use tracing::{Level, error, info, warn};
 use tracing_subscriber::EnvFilter;
 use utils::{Config, VERSION};
 
+mod agent;
+mod config;
+mod config_poller;
+mod diagnose;
+mod extract;
 mod heartbeat;
+mod http_auth;
+mod http_client;
 mod monitor_runner;
+mod proxy;
 mod pulse_queue;
+mod tls;
 mod utils;
 mod ws_client;
 mod services {
+	pub mod composite;
 	pub mod http;
+	pub mod http_steps;
 	pub mod icmp;
 	pub mod imap;
 	pub mod minecraft;
@@ -27,15 +42,43 @@ mod services {
 	pub mod ws;
 }
 
-use monitor_runner::MonitorRunner;
+use config_poller::ConfigPoller;
+use monitor_runner::{MonitorRunner, PulseRoute, PulseRoutes};
 use ws_client::WsClient;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
-	/// Path to config.toml file (optional if using PULSE_SERVER_URL)
-	#[arg(short, long, default_value_t = String::from("config.toml"))]
-	config: String,
+	/// Path to a config file (.toml, .json, .yaml) or directory of them (defaults to config.toml).
+	/// Combined with PULSE_SERVER_URL, its monitors run alongside the server-provided ones.
+	#[arg(short, long)]
+	config: Option<String>,
+
+	#[command(subcommand)]
+	command: Option<Command>,
+}
+
+impl Args {
+	fn config_path(&self) -> &str {
+		self.config.as_deref().unwrap_or("config.toml")
+	}
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+	/// Inspect the config file
+	Config {
+		#[command(subcommand)]
+		action: ConfigCommand,
+	},
+	/// Print a JSON Schema for config files
+	Schema,
+}
+
+#[derive(Subcommand, Debug)]
+enum ConfigCommand {
+	/// Print the fully expanded configuration (defaults and templates applied)
+	Render,
 }
 
 /// Configuration mode
@@ -43,87 +86,361 @@ enum ConfigMode {
 	/// Use local config.toml file
 	File(Config),
 	/// Use WebSocket connection to UptimeMonitor-Server
-	WebSocket { server_url: String, token: String },
+	WebSocket { servers: Vec<(String, String)> },
+	/// WebSocket connection plus monitors from a local config file
+	Hybrid {
+		servers: Vec<(String, String)>,
+		local: Config,
+	},
+	/// Poll configuration from an HTTP endpoint
+	Http(ConfigPoller),
 }
 
-fn load_env_config() -> Option<(String, String)> {
+/// Server subscriptions from `PULSE_SERVER_URL` + `PULSE_TOKEN` and `PULSE_SERVERS`.
+fn load_env_config() -> Vec<(String, String)> {
 	let server_url = std::env::var("PULSE_SERVER_URL").ok();
 	let token = std::env::var("PULSE_TOKEN").ok();
 
-	match (server_url, token) {
-		(Some(url), Some(tok)) if !url.is_empty() && !tok.is_empty() => Some((url, tok)),
-		_ => None,
+	let mut servers = match (server_url, token) {
+		(Some(url), Some(tok)) if !url.is_empty() && !tok.is_empty() => vec![(url, tok)],
+		_ => Vec::new(),
+	};
+
+	if let Ok(raw) = std::env::var("PULSE_SERVERS") {
+		for server in ws_client::parse_server_list(&raw) {
+			if !servers.contains(&server) {
+				servers.push(server);
+			}
+		}
 	}
+
+	servers
 }
 
 fn determine_config_mode(args: &Args) -> Result<ConfigMode, Box<dyn std::error::Error>> {
 	// First, check for environment variables
-	if let Some((server_url, token)) = load_env_config() {
-		info!("Using WebSocket mode with server: {}", server_url);
-		return Ok(ConfigMode::WebSocket { server_url, token });
+	let servers = load_env_config();
+	if !servers.is_empty() {
+		let urls: Vec<&str> = servers.iter().map(|(url, _)| url.as_str()).collect();
+
+		// An explicit --config adds local monitors on top of the server's
+		if let Some(config_path) = &args.config {
+			let local = config::load_config_file(config_path)?;
+			info!(
+				"Using hybrid mode with servers: {} and config file: {}",
+				urls.join(", "),
+				config_path
+			);
+			return Ok(ConfigMode::Hybrid { servers, local });
+		}
+
+		info!("Using WebSocket mode with servers: {}", urls.join(", "));
+		return Ok(ConfigMode::WebSocket { servers });
+	}
+
+	// Then an HTTP config endpoint
+	if let Some(poller) = ConfigPoller::from_env() {
+		info!("Using HTTP config mode with endpoint: {}", poller.url());
+		return Ok(ConfigMode::Http(poller));
 	}
 
 	// Fall back to config file
-	let config_path = &args.config;
+	let config_path = args.config_path();
 
 	if fs::metadata(config_path).is_ok() {
-		let toml_string = fs::read_to_string(config_path)?;
-		let config: Config = toml::from_str(&toml_string)?;
+		let config = config::load_config_file(config_path)?;
 		info!("Using config file: {}", config_path);
 		return Ok(ConfigMode::File(config));
 	}
 
 	Err(
 		format!(
-			"No configuration found. Either set PULSE_SERVER_URL and PULSE_TOKEN environment variables, \
-		or provide a config.toml file at '{}'",
+			"No configuration found. Either set PULSE_SERVER_URL and PULSE_TOKEN (or PULSE_SERVERS) environment variables, \
+		or provide a config.toml file or directory at '{}'",
 			config_path
 		)
 		.into(),
 	)
 }
 
-async fn run_file_mode(config: Config) {
+/// Every file contributing to the config plus the directories holding them or searched by
+/// `include` globs, as a new file only changes its directory's modification time.
+/// Finding them parses every file, so this only runs at startup and after a change.
+fn watched_paths(config_path: &str) -> Result<Vec<PathBuf>, String> {
+	let files = config::watched_files(config_path).map_err(|e| e.to_string())?;
+	let mut paths: Vec<PathBuf> = files
+		.iter()
+		.filter_map(|file| file.parent().map(PathBuf::from))
+		.chain(files.iter().cloned())
+		.collect();
+	paths.sort();
+	paths.dedup();
+	Ok(paths)
+}
+
+/// `paths` plus every file now inside the watched directories, watched while the config fails
+/// to parse so that fixing a newly added file is noticed as well.
+fn with_directory_entries(paths: &[PathBuf]) -> Vec<PathBuf> {
+	let mut extended: Vec<PathBuf> = paths
+		.iter()
+		.filter(|path| path.is_dir())
+		.filter_map(|dir| fs::read_dir(dir).ok())
+		.flatten()
+		.filter_map(|entry| entry.ok().map(|entry| entry.path()))
+		.chain(paths.iter().cloned())
+		.collect();
+	extended.sort();
+	extended.dedup();
+	extended
+}
+
+/// Modification times of `paths`, used to detect changes without reading the files.
+fn config_snapshot(paths: &[PathBuf]) -> Vec<(PathBuf, Option<SystemTime>)> {
+	paths
+		.iter()
+		.map(|path| {
+			let modified = fs::metadata(path).and_then(|m| m.modified()).ok();
+			(path.clone(), modified)
+		})
+		.collect()
+}
+
+/// Watch the config files and send each successfully reloaded config.
+/// The channel closes immediately when `PULSE_CONFIG_RELOAD_INTERVAL` is 0.
+fn watch_config_file(config_path: String) -> mpsc::Receiver<Config> {
+	let (tx, rx) = mpsc::channel::<Config>(8);
+
+	let reload_interval: u64 = std::env::var("PULSE_CONFIG_RELOAD_INTERVAL")
+		.ok()
+		.and_then(|v| v.parse().ok())
+		.unwrap_or(5);
+
+	if reload_interval == 0 {
+		return rx;
+	}
+
+	tokio::spawn(async move {
+		let mut paths = watched_paths(&config_path).unwrap_or_else(|e| {
+			warn!("Failed to scan config files: {}", e);
+			vec![PathBuf::from(&config_path)]
+		});
+		let mut last_snapshot = config_snapshot(&paths);
+
+		loop {
+			sleep(Duration::from_secs(reload_interval)).await;
+
+			if config_snapshot(&paths) == last_snapshot {
+				continue;
+			}
+
+			// Something changed: rescan, as includes may have changed too. A file that fails to
+			// parse keeps the last good paths, so it is reported once rather than on every tick.
+			match watched_paths(&config_path) {
+				Ok(scanned) => paths = scanned,
+				Err(e) => {
+					error!(
+						"Failed to reload config, keeping previous configuration: {}",
+						e
+					);
+					paths = with_directory_entries(&paths);
+					last_snapshot = config_snapshot(&paths);
+					continue;
+				}
+			}
+			last_snapshot = config_snapshot(&paths);
+
+			// The error type isn't Send, so don't hold it across the await below
+			match config::load_config_file(&config_path).map_err(|e| e.to_string()) {
+				Ok(config) => {
+					info!(
+						"Config files changed, reloading {} monitors",
+						config.monitors.len()
+					);
+					if tx.send(config).await.is_err() {
+						return;
+					}
+				}
+				Err(e) => {
+					error!(
+						"Failed to reload config, keeping previous configuration: {}",
+						e
+					);
+				}
+			}
+		}
+	});
+
+	rx
+}
+
+async fn run_file_mode(config: Config, config_path: String) {
 	let runner = MonitorRunner::new();
 	runner.start_monitors(&config).await;
 
+	let mut config_rx = watch_config_file(config_path);
+	while let Some(config) = config_rx.recv().await {
+		runner.update_monitors(&config).await;
+	}
+
+	// Reloading is disabled
 	loop {
 		sleep(Duration::from_secs(3600)).await;
 	}
 }
 
-async fn run_websocket_mode(server_url: String, token: String) {
-	let client = Arc::new(WsClient::new(&server_url, &token));
+async fn run_websocket_mode(servers: Vec<(String, String)>, local: Option<(Config, String)>) {
+	// One connection per subscription, each routing pulses for the monitors it assigned
+	let mut routes = PulseRoutes::default();
+	let mut clients = Vec::new();
+
+	for (i, (server_url, token)) in servers.into_iter().enumerate() {
+		let origin = format!("server-{}", i + 1);
+		let client = WsClient::new(&server_url, &token);
+
+		// Get the pulse sender before starting the client
+		routes.insert(
+			&origin,
+			PulseRoute {
+				server_url,
+				pulse_sender: client.get_pulse_sender(),
+			},
+		);
+		clients.push((origin, client));
+	}
+
+	// Create runner with WebSocket pulse senders; all subscriptions share its scheduler
+	let runner = MonitorRunner::with_websocket(routes);
 
-	// Get the pulse sender before starting the client
-	let pulse_sender = client.get_pulse_sender();
+	// Config updates arrive tagged with the origin of the connection
+	let (update_tx, mut update_rx) = mpsc::channel::<(String, Config)>(32);
+	let mut origins = Vec::new();
 
-	let mut config_rx = client.start().await;
+	for (origin, client) in clients {
+		let client = Arc::new(
+			client
+				.with_commands(&origin, runner.command_sender())
+				.with_status(runner.stats()),
+		);
+		let mut config_rx = client.start().await;
+		let update_tx = update_tx.clone();
+		let client_origin = origin.clone();
+		tokio::spawn(async move {
+			while let Some(config) = config_rx.recv().await {
+				let config = config::with_origin(config, &client_origin);
+				if update_tx
+					.send((client_origin.clone(), config))
+					.await
+					.is_err()
+				{
+					return;
+				}
+			}
+		});
 
-	// Create runner with WebSocket pulse sender
-	let runner = MonitorRunner::with_websocket(server_url, pulse_sender);
+		origins.push(origin);
+	}
+	drop(update_tx);
+
+	// Local monitors are namespaced so server updates never replace them
+	let (mut local_config, mut local_rx) = match local {
+		Some((config, config_path)) => {
+			let config = config::with_origin(config, config::LOCAL_ORIGIN);
+			info!("Starting {} local monitors", config.monitors.len());
+			runner.update_monitors(&config).await;
+			(Some(config), Some(watch_config_file(config_path)))
+		}
+		None => (None, None),
+	};
+	let mut server_configs: HashMap<String, Config> = HashMap::new();
 
 	info!("Waiting for configuration from server...");
 
-	// Wait for configuration updates
+	loop {
+		tokio::select! {
+			update = update_rx.recv() => {
+				let Some((origin, config)) = update else {
+					break;
+				};
+				info!(
+					"Applying new configuration with {} monitors from {}",
+					config.monitors.len(),
+					origin
+				);
+				server_configs.insert(origin, config);
+			}
+			Some(config) = async { local_rx.as_mut()?.recv().await } => {
+				local_config = Some(config::with_origin(config, config::LOCAL_ORIGIN));
+			}
+		}
+
+		// Update monitors with the combined config, in subscription order
+		let merged = config::merge_origins(
+			origins
+				.iter()
+				.filter_map(|origin| server_configs.get(origin)),
+			local_config.as_ref(),
+		);
+		runner.update_monitors(&merged).await;
+	}
+
+	// This should only happen if the channel is closed
+	error!("Configuration channel closed unexpectedly");
+}
+
+fn run_command(args: &Args, command: &Command) -> Result<(), Box<dyn std::error::Error>> {
+	match command {
+		Command::Config {
+			action: ConfigCommand::Render,
+		} => {
+			print!("{}", config::render_config_file(args.config_path())?);
+		}
+		Command::Schema => {
+			println!(
+				"{}",
+				serde_json::to_string_pretty(&config::config_schema())?
+			);
+		}
+	}
+	Ok(())
+}
+
+async fn run_http_config_mode(poller: ConfigPoller) {
+	let mut config_rx = Arc::new(poller).start().await;
+
+	let runner = MonitorRunner::new();
+
+	info!("Waiting for configuration from endpoint...");
+
 	while let Some(config) = config_rx.recv().await {
 		info!(
 			"Applying new configuration with {} monitors",
 			config.monitors.len()
 		);
 
-		// Update monitors with new config
 		runner.update_monitors(&config).await;
 	}
 
-	// This should only happen if the channel is closed
 	error!("Configuration channel closed unexpectedly");
 }
 
 #[tokio::main]
 async fn main() {
+	// Anchor the uptime reported in agent-status
+	agent::started_at();
+
 	let _ = dotenvy::dotenv();
 
+	let args: Args = Args::parse();
+
+	// Commands print to stdout, so they run before logging is set up
+	if let Some(command) = &args.command {
+		if let Err(e) = run_command(&args, command) {
+			eprintln!("Error: {}", e);
+			std::process::exit(1);
+		}
+		return;
+	}
+
 	let log_level: String = std::env::var("PULSE_LOG_LEVEL")
 		.unwrap_or("INFO".to_string())
 		.to_uppercase();
@@ -147,18 +464,35 @@ async fn main() {
 		.install_default()
 		.expect("Failed to install rustls crypto provider");
 
-	let args: Args = Args::parse();
-
 	info!("PulseMonitor {}", VERSION);
 
+	// Fail fast on unreadable certificates instead of on the first connection attempt
+	if let Err(e) = tls::server_tls_config() {
+		error!("TLS configuration error: {}", e);
+		std::process::exit(1);
+	}
+
 	match determine_config_mode(&args) {
 		Ok(ConfigMode::File(config)) => {
 			info!("Mode: Local config file");
-			run_file_mode(config).await;
+			run_file_mode(config, args.config_path().to_string()).await;
+		}
+		Ok(ConfigMode::WebSocket { servers }) => {
+			info!("Mode: WebSocket ({} servers)", servers.len());
+			run_websocket_mode(servers, None).await;
+		}
+		Ok(ConfigMode::Hybrid { servers, local }) => {
+			info!(
+				"Mode: Hybrid ({} servers + {})",
+				servers.len(),
+				args.config_path()
+			);
+			let local = Some((local, args.config_path().to_string()));
+			run_websocket_mode(servers, local).await;
 		}
-		Ok(ConfigMode::WebSocket { server_url, token }) => {
-			info!("Mode: WebSocket ({})", server_url);
-			run_websocket_mode(server_url, token).await;
+		Ok(ConfigMode::Http(poller)) => {
+			info!("Mode: HTTP config ({})", poller.url());
+			run_http_config_mode(poller).await;
 		}
 		Err(e) => {
 			error!("Configuration error: {}", e);
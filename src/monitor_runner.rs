@@ -1,6 +1,9 @@
+use crate::config::redact_secrets;
 use crate::heartbeat::send_heartbeat;
 use crate::services::{
+	composite::is_composite_online,
 	http::is_http_online,
+	http_steps::is_http_steps_online,
 	icmp::is_icmp_online,
 	imap::is_imap_online,
 	minecraft::{is_minecraft_bedrock_online, is_minecraft_java_online},
@@ -15,15 +18,16 @@ use crate::services::{
 	ws::is_ws_online,
 };
 use crate::utils::{CheckResult, Config, Monitor, PushMessage};
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 
 use std::cmp::Ordering;
 use std::collections::{BinaryHeap, HashMap};
 use std::hash::{Hash, Hasher};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering as AtomicOrdering};
 use std::time::Instant;
 
-use tokio::sync::{RwLock, Semaphore, mpsc, oneshot, watch};
+use tokio::sync::{OwnedSemaphorePermit, RwLock, Semaphore, mpsc, oneshot, watch};
 use tokio::task::JoinHandle;
 use tokio::time::{Duration, Instant as TokioInstant, sleep_until};
 use tracing::{error, info, warn};
@@ -35,6 +39,43 @@ fn round_to_3_decimals(dec: f64) -> f64 {
 /// Type alias for the pulse sender
 pub type PulseSender = Arc<RwLock<Option<mpsc::Sender<PushMessage>>>>;
 
+/// Server connection that receives pulses for a set of monitors
+#[derive(Clone)]
+pub struct PulseRoute {
+	pub server_url: String,
+	pub pulse_sender: PulseSender,
+}
+
+/// Pulse routes keyed by monitor origin. Monitors without a matching origin
+/// (e.g. local monitors in hybrid mode) use the first route added.
+#[derive(Clone, Default)]
+pub struct PulseRoutes {
+	by_origin: HashMap<String, PulseRoute>,
+	default_origin: Option<String>,
+}
+
+impl PulseRoutes {
+	pub fn insert(&mut self, origin: &str, route: PulseRoute) {
+		if self.default_origin.is_none() {
+			self.default_origin = Some(origin.to_string());
+		}
+		self.by_origin.insert(origin.to_string(), route);
+	}
+
+	fn for_monitor(&self, monitor: &Monitor) -> Option<&PulseRoute> {
+		monitor
+			.origin
+			.as_ref()
+			.and_then(|origin| self.by_origin.get(origin))
+			.or_else(|| {
+				self
+					.default_origin
+					.as_ref()
+					.and_then(|origin| self.by_origin.get(origin))
+			})
+	}
+}
+
 #[derive(Clone)]
 struct MonitorEntry {
 	monitor: Monitor,
@@ -66,6 +107,58 @@ impl PartialEq for DueItem {
 }
 impl Eq for DueItem {}
 
+/// Action requested by a server for one of its monitors
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandAction {
+	RunNow,
+	Pause { until: Option<DateTime<Utc>> },
+	Resume,
+}
+
+/// Command routed into the scheduler; the outcome is sent back on `reply`
+pub struct SchedulerCommand {
+	pub origin: Option<String>,
+	pub token: String,
+	pub action: CommandAction,
+	pub reply: oneshot::Sender<Result<String, String>>,
+}
+
+/// Channel for sending commands to the scheduler
+pub type CommandSender = mpsc::Sender<SchedulerCommand>;
+
+/// Counters updated by the scheduler and read for status reporting
+#[derive(Default)]
+pub struct SchedulerStats {
+	active_monitors: AtomicUsize,
+	checks_started: AtomicU64,
+	in_flight: AtomicUsize,
+	max_concurrent: AtomicUsize,
+	/// How late the most recent dispatch ran compared to its due time
+	lag_ms: AtomicU64,
+}
+
+/// Point-in-time copy of [`SchedulerStats`]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SchedulerSnapshot {
+	pub active_monitors: usize,
+	pub checks_started: u64,
+	pub in_flight: usize,
+	pub max_concurrent: usize,
+	pub lag_ms: u64,
+}
+
+impl SchedulerStats {
+	pub fn snapshot(&self) -> SchedulerSnapshot {
+		SchedulerSnapshot {
+			active_monitors: self.active_monitors.load(AtomicOrdering::Relaxed),
+			checks_started: self.checks_started.load(AtomicOrdering::Relaxed),
+			in_flight: self.in_flight.load(AtomicOrdering::Relaxed),
+			max_concurrent: self.max_concurrent.load(AtomicOrdering::Relaxed),
+			lag_ms: self.lag_ms.load(AtomicOrdering::Relaxed),
+		}
+	}
+}
+
 struct SchedulerHandle {
 	config_tx: watch::Sender<Config>,
 	#[allow(unused)]
@@ -76,30 +169,42 @@ struct SchedulerHandle {
 
 /// Manages running monitors via a single centralized scheduler task
 pub struct MonitorRunner {
-	server_url: Option<String>,
-	pulse_sender: Option<PulseSender>,
+	routes: Arc<PulseRoutes>,
 	scheduler: Arc<RwLock<Option<SchedulerHandle>>>,
+	command_tx: CommandSender,
+	/// Handed to the scheduler task when it starts
+	command_rx: std::sync::Mutex<Option<mpsc::Receiver<SchedulerCommand>>>,
+	stats: Arc<SchedulerStats>,
 }
 
 impl MonitorRunner {
 	/// Create a new MonitorRunner for file mode (no server_url needed)
 	pub fn new() -> Self {
-		MonitorRunner {
-			server_url: None,
-			pulse_sender: None,
-			scheduler: Arc::new(RwLock::new(None)),
-		}
+		Self::with_websocket(PulseRoutes::default())
 	}
 
-	/// Create a new MonitorRunner for WebSocket mode with server_url and pulse sender
-	pub fn with_websocket(server_url: String, pulse_sender: PulseSender) -> Self {
+	/// Create a new MonitorRunner for WebSocket mode with one pulse route per server subscription
+	pub fn with_websocket(routes: PulseRoutes) -> Self {
+		let (command_tx, command_rx) = mpsc::channel(64);
 		MonitorRunner {
-			server_url: Some(server_url),
-			pulse_sender: Some(pulse_sender),
+			routes: Arc::new(routes),
 			scheduler: Arc::new(RwLock::new(None)),
+			command_tx,
+			command_rx: std::sync::Mutex::new(Some(command_rx)),
+			stats: Arc::new(SchedulerStats::default()),
 		}
 	}
 
+	/// Scheduler counters for agent status reports
+	pub fn stats(&self) -> Arc<SchedulerStats> {
+		self.stats.clone()
+	}
+
+	/// Sender for run-now / pause / resume commands
+	pub fn command_sender(&self) -> CommandSender {
+		self.command_tx.clone()
+	}
+
 	/// Start monitors from config.
 	pub async fn start_monitors(&self, config: &Config) -> Vec<JoinHandle<()>> {
 		let max_concurrent_checks = config
@@ -121,15 +226,17 @@ impl MonitorRunner {
 
 				let jitter_ms_max: u64 = 500;
 
-				let server_url = self.server_url.clone();
-				let pulse_sender = self.pulse_sender.clone();
+				let routes = self.routes.clone();
+				let command_rx = self.command_rx.lock().unwrap().take();
+				let stats = self.stats.clone();
 
 				let handle = tokio::spawn(async move {
 					run_scheduler_loop(
 						config_rx,
 						stop_rx,
-						server_url,
-						pulse_sender,
+						command_rx,
+						routes,
+						stats,
 						max_concurrent_checks,
 						jitter_ms_max,
 					)
@@ -178,17 +285,26 @@ impl MonitorRunner {
 async fn run_scheduler_loop(
 	mut config_rx: watch::Receiver<Config>,
 	mut stop_rx: oneshot::Receiver<()>,
-	server_url: Option<String>,
-	pulse_sender: Option<PulseSender>,
+	mut command_rx: Option<mpsc::Receiver<SchedulerCommand>>,
+	routes: Arc<PulseRoutes>,
+	stats: Arc<SchedulerStats>,
 	max_concurrent_checks: usize,
 	jitter_ms_max: u64,
 ) {
 	let sem = Arc::new(Semaphore::new(max_concurrent_checks));
+	stats
+		.max_concurrent
+		.store(max_concurrent_checks, AtomicOrdering::Relaxed);
 
 	let mut entries: HashMap<String, MonitorEntry> = HashMap::new();
 	let mut heap: BinaryHeap<DueItem> = BinaryHeap::new();
+	// Paused monitor keys, with an optional time at which they resume
+	let mut paused: HashMap<String, Option<DateTime<Utc>>> = HashMap::new();
 
 	rebuild_state(&config_rx.borrow(), &mut entries, &mut heap, jitter_ms_max);
+	stats
+		.active_monitors
+		.store(entries.len(), AtomicOrdering::Relaxed);
 
 	loop {
 		tokio::select! {
@@ -204,9 +320,16 @@ async fn run_scheduler_loop(
 				}
 				let cfg = config_rx.borrow().clone();
 				rebuild_state(&cfg, &mut entries, &mut heap, jitter_ms_max);
+				paused.retain(|key, _| entries.contains_key(key));
+				stats.active_monitors.store(entries.len(), AtomicOrdering::Relaxed);
 				info!("Scheduler applied new config: {} monitors", entries.len());
 			}
 
+			Some(command) = async { command_rx.as_mut()?.recv().await } => {
+				let result = handle_command(&command, &entries, &mut paused, &sem, &routes, &stats);
+				let _ = command.reply.send(result);
+			}
+
 			_ = async {
 				// sleep until next due item, or a short interval if empty
 				if let Some(next) = heap.peek().cloned() {
@@ -222,7 +345,7 @@ async fn run_scheduler_loop(
 					tokio::time::sleep(Duration::from_millis(200)).await;
 				}
 			} => {
-				dispatch_due(&entries, &mut heap, sem.clone(), server_url.clone(), pulse_sender.clone(), jitter_ms_max).await;
+				dispatch_due(&entries, &mut heap, &mut paused, sem.clone(), &routes, &stats, jitter_ms_max).await;
 			}
 		}
 	}
@@ -243,7 +366,7 @@ fn rebuild_state(
 		if !m.enabled {
 			continue;
 		}
-		let key = m.token.clone().unwrap_or_else(|| m.name.clone());
+		let key = scheduler_key(m);
 
 		entries.insert(key.clone(), MonitorEntry { monitor: m.clone() });
 
@@ -258,9 +381,10 @@ fn rebuild_state(
 async fn dispatch_due(
 	entries: &HashMap<String, MonitorEntry>,
 	heap: &mut BinaryHeap<DueItem>,
+	paused: &mut HashMap<String, Option<DateTime<Utc>>>,
 	sem: Arc<Semaphore>,
-	server_url: Option<String>,
-	pulse_sender: Option<PulseSender>,
+	routes: &PulseRoutes,
+	stats: &Arc<SchedulerStats>,
 	jitter_ms_max: u64,
 ) {
 	let now = TokioInstant::now();
@@ -276,6 +400,8 @@ async fn dispatch_due(
 			break;
 		}
 		let item = heap.pop().unwrap();
+		let lag = now.duration_since(item.when).as_millis() as u64;
+		stats.lag_ms.store(lag, AtomicOrdering::Relaxed);
 
 		let Some(entry) = entries.get(&item.key) else {
 			continue;
@@ -290,6 +416,11 @@ async fn dispatch_due(
 			key: item.key.clone(),
 		});
 
+		if is_paused(paused, &item.key, Utc::now()) {
+			processed += 1;
+			continue;
+		}
+
 		// bounded concurrency: if no permits, requeue soon and move on
 		let permit = match sem.clone().try_acquire_owned() {
 			Ok(p) => p,
@@ -303,19 +434,111 @@ async fn dispatch_due(
 			}
 		};
 
-		let monitor = entry.monitor.clone();
-		let server_url = server_url.clone();
-		let pulse_sender = pulse_sender.clone();
-
-		tokio::spawn(async move {
-			let _permit = permit;
-			run_single_check(&monitor, server_url.as_deref(), pulse_sender.as_ref()).await;
-		});
+		spawn_check(&entry.monitor, permit, routes, stats);
 
 		processed += 1;
 	}
 }
 
+fn spawn_check(
+	monitor: &Monitor,
+	permit: OwnedSemaphorePermit,
+	routes: &PulseRoutes,
+	stats: &Arc<SchedulerStats>,
+) {
+	let monitor = monitor.clone();
+	let route = routes.for_monitor(&monitor).cloned();
+	let stats = stats.clone();
+
+	stats.checks_started.fetch_add(1, AtomicOrdering::Relaxed);
+	stats.in_flight.fetch_add(1, AtomicOrdering::Relaxed);
+
+	tokio::spawn(async move {
+		let _permit = permit;
+		run_single_check(
+			&monitor,
+			route.as_ref().map(|r| r.server_url.as_str()),
+			route.as_ref().map(|r| &r.pulse_sender),
+		)
+		.await;
+		stats.in_flight.fetch_sub(1, AtomicOrdering::Relaxed);
+	});
+}
+
+/// Whether the monitor is paused at `now`; expired pauses are removed.
+fn is_paused(
+	paused: &mut HashMap<String, Option<DateTime<Utc>>>,
+	key: &str,
+	now: DateTime<Utc>,
+) -> bool {
+	match paused.get(key) {
+		None => false,
+		Some(Some(until)) if *until <= now => {
+			paused.remove(key);
+			false
+		}
+		Some(_) => true,
+	}
+}
+
+fn handle_command(
+	command: &SchedulerCommand,
+	entries: &HashMap<String, MonitorEntry>,
+	paused: &mut HashMap<String, Option<DateTime<Utc>>>,
+	sem: &Arc<Semaphore>,
+	routes: &PulseRoutes,
+	stats: &Arc<SchedulerStats>,
+) -> Result<String, String> {
+	let key = namespaced_key(command.origin.as_deref(), command.token.clone());
+	let entry = entries
+		.get(&key)
+		.ok_or_else(|| format!("Unknown monitor token '{}'", command.token))?;
+
+	match &command.action {
+		CommandAction::RunNow => {
+			let permit = sem
+				.clone()
+				.try_acquire_owned()
+				.map_err(|_| "Concurrency limit reached, try again later".to_string())?;
+			spawn_check(&entry.monitor, permit, routes, stats);
+			info!("Running '{}' now on server request", entry.monitor.name);
+			Ok("Check started".to_string())
+		}
+		CommandAction::Pause { until } => {
+			paused.insert(key, *until);
+			info!("Paused '{}' on server request", entry.monitor.name);
+			Ok(match until {
+				Some(until) => format!("Paused until {}", until.to_rfc3339()),
+				None => "Paused".to_string(),
+			})
+		}
+		CommandAction::Resume => {
+			if paused.remove(&key).is_none() {
+				return Ok("Not paused".to_string());
+			}
+			info!("Resumed '{}' on server request", entry.monitor.name);
+			Ok("Resumed".to_string())
+		}
+	}
+}
+
+/// Scheduler key: token or name, prefixed with the origin so monitors from different
+/// sources never replace each other.
+fn scheduler_key(monitor: &Monitor) -> String {
+	let key = monitor
+		.token
+		.clone()
+		.unwrap_or_else(|| monitor.name.clone());
+	namespaced_key(monitor.origin.as_deref(), key)
+}
+
+fn namespaced_key(origin: Option<&str>, key: String) -> String {
+	match origin {
+		Some(origin) => format!("{}:{}", origin, key),
+		None => key,
+	}
+}
+
 /// Stable jitter based on hashing a string key.
 /// This avoids needing RNG and remains stable across restarts.
 fn stable_jitter_ms(key: &str, jitter_ms_max: u64) -> u64 {
@@ -324,7 +547,48 @@ fn stable_jitter_ms(key: &str, jitter_ms_max: u64) -> u64 {
 	}
 	let mut hasher = std::collections::hash_map::DefaultHasher::new();
 	key.hash(&mut hasher);
-	(hasher.finish() % (jitter_ms_max + 1)) as u64
+	hasher.finish() % (jitter_ms_max + 1)
+}
+
+/// Run the service check configured on a monitor.
+pub async fn run_service_check(
+	monitor: &Monitor,
+) -> Result<CheckResult, Box<dyn std::error::Error + Send + Sync>> {
+	if monitor.http.is_some() {
+		is_http_online(monitor).await
+	} else if monitor.http_steps.is_some() {
+		is_http_steps_online(monitor).await
+	} else if monitor.ws.is_some() {
+		is_ws_online(monitor).await
+	} else if monitor.tcp.is_some() {
+		is_tcp_online(monitor).await
+	} else if monitor.udp.is_some() {
+		is_udp_online(monitor).await
+	} else if monitor.icmp.is_some() {
+		is_icmp_online(monitor).await
+	} else if monitor.smtp.is_some() {
+		is_smtp_online(monitor).await
+	} else if monitor.imap.is_some() {
+		is_imap_online(monitor).await
+	} else if monitor.mysql.is_some() {
+		is_mysql_online(monitor).await
+	} else if monitor.mssql.is_some() {
+		is_mssql_online(monitor).await
+	} else if monitor.postgresql.is_some() {
+		is_postgresql_online(monitor).await
+	} else if monitor.redis.is_some() {
+		is_redis_online(monitor).await
+	} else if monitor.minecraft_java.is_some() {
+		is_minecraft_java_online(monitor).await
+	} else if monitor.minecraft_bedrock.is_some() {
+		is_minecraft_bedrock_online(monitor).await
+	} else if monitor.snmp.is_some() {
+		is_snmp_online(monitor).await
+	} else if monitor.composite.is_some() {
+		is_composite_online(monitor).await
+	} else {
+		Ok(CheckResult::from_latency(None))
+	}
 }
 
 async fn run_single_check(
@@ -335,38 +599,7 @@ async fn run_single_check(
 	let start_check_time = Utc::now();
 	let start_time = Instant::now();
 
-	let result: Result<CheckResult, Box<dyn std::error::Error + Send + Sync>> =
-		if monitor.http.is_some() {
-			is_http_online(monitor).await
-		} else if monitor.ws.is_some() {
-			is_ws_online(monitor).await
-		} else if monitor.tcp.is_some() {
-			is_tcp_online(monitor).await
-		} else if monitor.udp.is_some() {
-			is_udp_online(monitor).await
-		} else if monitor.icmp.is_some() {
-			is_icmp_online(monitor).await
-		} else if monitor.smtp.is_some() {
-			is_smtp_online(monitor).await
-		} else if monitor.imap.is_some() {
-			is_imap_online(monitor).await
-		} else if monitor.mysql.is_some() {
-			is_mysql_online(monitor).await
-		} else if monitor.mssql.is_some() {
-			is_mssql_online(monitor).await
-		} else if monitor.postgresql.is_some() {
-			is_postgresql_online(monitor).await
-		} else if monitor.redis.is_some() {
-			is_redis_online(monitor).await
-		} else if monitor.minecraft_java.is_some() {
-			is_minecraft_java_online(monitor).await
-		} else if monitor.minecraft_bedrock.is_some() {
-			is_minecraft_bedrock_online(monitor).await
-		} else if monitor.snmp.is_some() {
-			is_snmp_online(monitor).await
-		} else {
-			Ok(CheckResult::from_latency(None))
-		};
+	let result = run_service_check(monitor).await;
 
 	let end_check_time = Utc::now();
 
@@ -392,13 +625,70 @@ async fn run_single_check(
 			)
 			.await
 			{
-				error!("Failed to send heartbeat for '{}': {}", monitor.name, e);
+				error!(
+					"Failed to send heartbeat for '{}': {}",
+					monitor.name,
+					redact_secrets(&e.to_string())
+				);
 			}
 		}
 		Err(err) => {
 			if monitor.debug.unwrap_or(false) {
-				error!("Monitor '{}' failed: {}", monitor.name, err);
+				error!(
+					"Monitor '{}' failed: {}",
+					monitor.name,
+					redact_secrets(&err.to_string())
+				);
 			}
 		}
 	}
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn command(token: &str, action: CommandAction) -> SchedulerCommand {
+		SchedulerCommand {
+			origin: Some("server-1".to_string()),
+			token: token.to_string(),
+			action,
+			reply: oneshot::channel().0,
+		}
+	}
+
+	#[test]
+	fn test_pause_and_resume_commands() {
+		let monitor = Monitor {
+			name: "API".to_string(),
+			token: Some("tk_api".to_string()),
+			origin: Some("server-1".to_string()),
+			..Default::default()
+		};
+		let entries = HashMap::from([(scheduler_key(&monitor), MonitorEntry { monitor })]);
+		let mut paused = HashMap::new();
+		let sem = Arc::new(Semaphore::new(1));
+		let routes = PulseRoutes::default();
+		let stats = Arc::new(SchedulerStats::default());
+		let now = Utc::now();
+
+		let until = now + chrono::Duration::minutes(10);
+		let pause = command("tk_api", CommandAction::Pause { until: Some(until) });
+		assert!(handle_command(&pause, &entries, &mut paused, &sem, &routes, &stats).is_ok());
+		assert!(is_paused(&mut paused, "server-1:tk_api", now));
+		assert!(!is_paused(&mut paused, "server-1:tk_api", until));
+		assert!(paused.is_empty());
+
+		let pause = command("tk_api", CommandAction::Pause { until: None });
+		handle_command(&pause, &entries, &mut paused, &sem, &routes, &stats).unwrap();
+		let resume = command("tk_api", CommandAction::Resume);
+		assert_eq!(
+			handle_command(&resume, &entries, &mut paused, &sem, &routes, &stats),
+			Ok("Resumed".to_string())
+		);
+		assert!(!is_paused(&mut paused, "server-1:tk_api", now));
+
+		let unknown = command("tk_other", CommandAction::Resume);
+		assert!(handle_command(&unknown, &entries, &mut paused, &sem, &routes, &stats).is_err());
+	}
+}
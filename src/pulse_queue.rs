@@ -61,6 +61,18 @@ struct PulseQueueInner {
 	order: VecDeque<String>,
 	/// Pulse data keyed by pulse_id for O(1) lookup/removal
 	pulses: HashMap<String, QueuedPulse>,
+	/// Pulses dropped because the queue was full
+	dropped_queue_full: u64,
+	/// Pulses dropped after exceeding max retries
+	dropped_max_retries: u64,
+}
+
+/// Point-in-time view of the queue for status reporting
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PulseQueueStats {
+	pub pending: usize,
+	pub dropped_queue_full: u64,
+	pub dropped_max_retries: u64,
 }
 
 #[derive(Clone)]
@@ -76,6 +88,8 @@ impl PulseQueue {
 			inner: Arc::new(Mutex::new(PulseQueueInner {
 				order: VecDeque::with_capacity(capacity),
 				pulses: HashMap::with_capacity(capacity),
+				dropped_queue_full: 0,
+				dropped_max_retries: 0,
 			})),
 			config,
 		}
@@ -89,6 +103,7 @@ impl PulseQueue {
 		if inner.pulses.len() >= self.config.max_queue_size {
 			while let Some(old_id) = inner.order.pop_front() {
 				if let Some(dropped) = inner.pulses.remove(&old_id) {
+					inner.dropped_queue_full += 1;
 					warn!(
 						"Pulse queue full ({}), dropping oldest pulse {} for token {}",
 						self.config.max_queue_size, old_id, dropped.message.token
@@ -141,6 +156,7 @@ impl PulseQueue {
 
 			if pulse.attempts >= self.config.max_retries {
 				if let Some(removed) = inner.pulses.remove(&id) {
+					inner.dropped_max_retries += 1;
 					warn!(
 						"Pulse {} exceeded max retries ({}), dropping for token {}",
 						id, self.config.max_retries, removed.message.token
@@ -189,6 +205,7 @@ impl PulseQueue {
 			if pulse.attempts >= self.config.max_retries {
 				let token = pulse.message.token.clone();
 				inner.pulses.remove(&id);
+				inner.dropped_max_retries += 1;
 				warn!(
 					"Pulse {} exceeded max retries ({}), dropping for token {}",
 					id, self.config.max_retries, token
@@ -232,14 +249,24 @@ impl PulseQueue {
 			.retain(|_, p| p.attempts < self.config.max_retries);
 
 		let pruned = before - inner.pulses.len();
+		inner.dropped_max_retries += pruned as u64;
 		if pruned > 0 {
 			warn!("Pruned {} pulses that exceeded max retries", pruned);
 		}
 
-		let PulseQueueInner { order, pulses } = &mut *inner;
+		let PulseQueueInner { order, pulses, .. } = &mut *inner;
 		order.retain(|id| pulses.contains_key(id));
 	}
 
+	pub async fn stats(&self) -> PulseQueueStats {
+		let inner = self.inner.lock().await;
+		PulseQueueStats {
+			pending: inner.pulses.len(),
+			dropped_queue_full: inner.dropped_queue_full,
+			dropped_max_retries: inner.dropped_max_retries,
+		}
+	}
+
 	/*
 	pub async fn pending_count(&self) -> usize {
 		self.inner.lock().await.pulses.len()
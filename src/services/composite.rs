@@ -0,0 +1,185 @@
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Instant;
+
+use futures_util::future::join_all;
+
+use crate::monitor_runner::run_service_check;
+use crate::utils::{CheckResult, CompositePolicy, Monitor};
+
+type CheckFuture<'a> =
+	Pin<Box<dyn Future<Output = Result<CheckResult, Box<dyn Error + Send + Sync>>> + Send + 'a>>;
+
+/// Whether the number of passed sub-checks satisfies the policy.
+fn is_satisfied(policy: CompositePolicy, passed: usize, total: usize) -> bool {
+	match policy {
+		CompositePolicy::All => passed == total,
+		CompositePolicy::Any => passed > 0,
+		CompositePolicy::Quorum(n) => passed >= n,
+	}
+}
+
+/// Boxed because sub-checks recurse through `run_service_check`.
+pub fn is_composite_online(monitor: &Monitor) -> CheckFuture<'_> {
+	Box::pin(run_composite_check(monitor))
+}
+
+async fn run_composite_check(
+	monitor: &Monitor,
+) -> Result<CheckResult, Box<dyn Error + Send + Sync>> {
+	let composite = monitor
+		.composite
+		.as_ref()
+		.ok_or("Monitor does not contain composite configuration")?;
+
+	if composite.checks.is_empty() {
+		return Err("Composite monitor has no sub-checks".into());
+	}
+
+	let policy = composite.policy.unwrap_or_default();
+
+	let sub_checks: Vec<(String, Monitor)> = composite
+		.checks
+		.iter()
+		.enumerate()
+		.map(|(i, check)| {
+			let name = check.name(i);
+			let sub_monitor = check.to_monitor(monitor, &name)?;
+			Ok((name, sub_monitor))
+		})
+		.collect::<Result<_, String>>()?;
+
+	let outcomes = join_all(sub_checks.iter().map(|(_, sub_monitor)| async move {
+		let start = Instant::now();
+		let outcome = run_service_check(sub_monitor).await;
+		(outcome, start.elapsed().as_secs_f64() * 1000.0)
+	}))
+	.await;
+
+	let mut result = CheckResult::new();
+	let mut errors = Vec::new();
+	let mut passed = 0usize;
+	let mut max_latency: Option<f64> = None;
+
+	for ((name, _), (outcome, elapsed_ms)) in sub_checks.iter().zip(outcomes) {
+		match outcome {
+			Ok(sub_result) => {
+				passed += 1;
+				let latency = sub_result.latency().unwrap_or(elapsed_ms);
+				max_latency = Some(max_latency.map_or(latency, |m| m.max(latency)));
+
+				result.set(format!("{}_up", name), 1.0);
+				result.set(format!("{}_latency", name), latency);
+				for (key, value) in &sub_result.values {
+					if key != "latency" {
+						result.set(format!("{}_{}", name, key), *value);
+					}
+				}
+				for (key, value) in &sub_result.labels {
+					result.set_label(format!("{}_{}", name, key), value.clone());
+				}
+			}
+			Err(e) => {
+				result.set(format!("{}_up", name), 0.0);
+				errors.push(format!("{}: {}", name, e));
+			}
+		}
+	}
+
+	let total = sub_checks.len();
+
+	if !is_satisfied(policy, passed, total) {
+		return Err(
+			format!(
+				"Composite policy '{}' not satisfied ({}/{} passed): {}",
+				policy,
+				passed,
+				total,
+				errors.join("; ")
+			)
+			.into(),
+		);
+	}
+
+	if let Some(latency) = max_latency {
+		result.set("latency", latency);
+	}
+	result.set("passed", passed as f64);
+	result.set("failed", (total - passed) as f64);
+
+	Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::CompositeConfig;
+
+	fn policy(text: &str) -> Result<CompositePolicy, serde_json::Error> {
+		serde_json::from_value(serde_json::json!(text))
+	}
+
+	#[test]
+	fn test_parse_policy() {
+		assert_eq!(policy("all").unwrap(), CompositePolicy::All);
+		assert_eq!(policy("ANY").unwrap(), CompositePolicy::Any);
+		assert_eq!(policy(" Quorum( 2 ) ").unwrap(), CompositePolicy::Quorum(2));
+		assert_eq!(
+			serde_json::to_value(CompositePolicy::Quorum(2)).unwrap(),
+			"quorum(2)"
+		);
+		assert!(policy("quorum(0)").is_err());
+		assert!(policy("quorum(x)").is_err());
+		assert_eq!(
+			policy("most").unwrap_err().to_string(),
+			"Unsupported composite policy 'most'. Supported: all, any, quorum(n)"
+		);
+	}
+
+	#[test]
+	fn test_is_satisfied() {
+		assert!(is_satisfied(CompositePolicy::All, 3, 3));
+		assert!(!is_satisfied(CompositePolicy::All, 2, 3));
+		assert!(is_satisfied(CompositePolicy::Any, 1, 3));
+		assert!(!is_satisfied(CompositePolicy::Any, 0, 3));
+		assert!(is_satisfied(CompositePolicy::Quorum(2), 2, 3));
+		assert!(!is_satisfied(CompositePolicy::Quorum(2), 1, 3));
+	}
+
+	#[test]
+	fn test_composite_is_validated_on_load() {
+		let load = |composite: serde_json::Value| {
+			serde_json::from_value::<CompositeConfig>(composite).map_err(|e| e.to_string())
+		};
+		let api = serde_json::json!({ "name": "api", "icmp": { "host": "127.0.0.1" } });
+
+		let error = load(serde_json::json!({
+			"checks": [api, { "name": "typo", "htp": { "method": "GET", "url": "https://example.com" } }]
+		}))
+		.unwrap_err();
+		assert!(
+			error.starts_with("Composite sub-check 'typo' has no service configuration"),
+			"{}",
+			error
+		);
+
+		let error = load(serde_json::json!({
+			"checks": [api, { "tcp": { "host": "db", "port": 5432 }, "udp": { "host": "db", "port": 53 } }]
+		}))
+		.unwrap_err();
+		assert_eq!(
+			error,
+			"Composite sub-check 'check2' must have exactly one service, found: tcp, udp"
+		);
+
+		let error =
+			load(serde_json::json!({ "policy": "quorum(3)", "checks": [api, api] })).unwrap_err();
+		assert_eq!(
+			error,
+			"Composite policy 'quorum(3)' can never pass with 2 sub-checks"
+		);
+		assert!(load(serde_json::json!({ "checks": [] })).is_err());
+		assert!(load(serde_json::json!({ "policy": "quorum(2)", "checks": [api, api] })).is_ok());
+	}
+}
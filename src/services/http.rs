@@ -1,215 +1,466 @@
-use crate::utils::{CheckResult, Monitor};
-use reqwest::Client;
+use crate::extract;
+use crate::http_auth;
+use crate::http_client::{ClientOptions, HttpClient, MAX_REDIRECTS, Request, Response};
+use crate::tls::TlsSettings;
+use crate::utils::{
+	CheckResult, HTTP_METHODS, HeaderAssertion, HttpConfig, Monitor, StatusMatcher,
+};
+use bytes::Bytes;
+use chrono::{SecondsFormat, Utc};
+use http::header::{CONTENT_TYPE, HeaderMap, HeaderName, HeaderValue};
+use http::{Method, StatusCode, Version};
 use std::{
+	collections::BTreeMap,
 	error::Error,
-	sync::OnceLock,
+	net::IpAddr,
+	path::PathBuf,
 	time::{Duration, Instant},
 };
-use tracing::{debug, warn};
-
-fn shared_client() -> &'static Client {
-	static CLIENT: OnceLock<Client> = OnceLock::new();
-	CLIENT.get_or_init(|| {
-		Client::builder()
-			.pool_max_idle_per_host(2)
-			.pool_idle_timeout(Duration::from_secs(15))
-			.hickory_dns(true)
-			.build()
-			.expect("Failed to build HTTP client")
-	})
+use url::Url;
+
+/// Whether `status` matches any `expectedStatus` entry
+fn status_matches(expected: &[StatusMatcher], status: u16) -> bool {
+	expected.iter().any(|matcher| matcher.matches(status))
+}
+
+fn describe_expected(expected: &[StatusMatcher]) -> String {
+	expected
+		.iter()
+		.map(ToString::to_string)
+		.collect::<Vec<_>>()
+		.join(", ")
+}
+
+/// Run the body assertions, naming the first one that fails
+fn check_body(http: &HttpConfig, body: &str) -> Result<(), String> {
+	if let Some(needle) = &http.body_contains
+		&& !body.contains(needle.as_str())
+	{
+		return Err(format!(
+			"bodyContains assertion failed: '{}' not found in response body",
+			needle
+		));
+	}
+
+	if let Some(needle) = &http.body_not_contains
+		&& body.contains(needle.as_str())
+	{
+		return Err(format!(
+			"bodyNotContains assertion failed: '{}' found in response body",
+			needle
+		));
+	}
+
+	if let Some(pattern) = &http.body_regex
+		&& !pattern.is_match(body)
+	{
+		return Err(format!(
+			"bodyRegex assertion failed: /{}/ did not match response body",
+			pattern
+		));
+	}
+
+	Ok(())
 }
 
-fn extract_json_value(json: &serde_json::Value, path: &str) -> Option<f64> {
-	let mut current = json;
+/// Run the `expectedHeaders` assertions, naming the first one that fails
+fn check_headers(http: &HttpConfig, headers: &HeaderMap) -> Result<(), String> {
+	let Some(expected) = &http.expected_headers else {
+		return Ok(());
+	};
+	let mut expected: Vec<_> = expected.iter().collect();
+	expected.sort_by(|a, b| a.0.cmp(b.0));
+
+	for (name, assertion) in expected {
+		let header = HeaderName::from_bytes(name.as_bytes())
+			.map_err(|_| format!("Invalid expectedHeaders name: '{}'", name))?;
+		let values: Vec<&str> = headers
+			.get_all(&header)
+			.iter()
+			.filter_map(|value| value.to_str().ok())
+			.collect();
+		let failed = |detail: String| {
+			Err(format!(
+				"expectedHeaders assertion failed: '{}' {}",
+				name, detail
+			))
+		};
 
-	for segment in path.split('.') {
-		if segment.is_empty() {
+		if values.is_empty() {
+			if *assertion != HeaderAssertion::Present(false) {
+				return failed("header missing".to_string());
+			}
 			continue;
 		}
+		let value = values.join(", ");
 
-		if let Some(index_str) = segment.strip_prefix('[').and_then(|s| s.strip_suffix(']')) {
-			if let Ok(index) = index_str.parse::<usize>() {
-				current = current.get(index)?;
-				continue;
+		match assertion {
+			HeaderAssertion::Present(true) => {}
+			HeaderAssertion::Present(false) => return failed(format!("present ('{}')", value)),
+			HeaderAssertion::Equals(expected) => {
+				if value != *expected {
+					return failed(format!("is '{}' (expected '{}')", value, expected));
+				}
+			}
+			HeaderAssertion::Matches { contains, regex } => {
+				if let Some(needle) = contains
+					&& !value.contains(needle.as_str())
+				{
+					return failed(format!("is '{}' (expected to contain '{}')", value, needle));
+				}
+				if let Some(pattern) = regex
+					&& !pattern.is_match(&value)
+				{
+					return failed(format!("is '{}' (expected to match /{}/)", value, pattern));
+				}
 			}
 		}
-
-		current = current.get(segment)?;
 	}
 
-	match current {
-		serde_json::Value::Number(n) => n.as_f64(),
-		serde_json::Value::String(s) => s.trim().parse::<f64>().ok(),
-		serde_json::Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
-		_ => None,
+	Ok(())
+}
+
+/// Fills `{timestampISO}` and `{timestampUnix}` with the current time
+pub fn timestamp_placeholders() -> impl Fn(&str) -> String {
+	let now = Utc::now();
+	let timestamp_iso = now.to_rfc3339_opts(SecondsFormat::Millis, true);
+	let timestamp_unix = now.timestamp_millis().to_string();
+	move |text: &str| {
+		text
+			.replace("{timestampISO}", &timestamp_iso)
+			.replace("{timestampUnix}", &timestamp_unix)
 	}
 }
 
-pub async fn is_http_online(
-	monitor: &Monitor,
-) -> Result<CheckResult, Box<dyn Error + Send + Sync>> {
-	let http = monitor
-		.http
-		.as_ref()
-		.ok_or("Monitor does not contain HTTP configuration")?;
+/// Whether `expectedStatus` accepts a 3xx, in which case redirects are not followed by default
+fn expects_redirect(http: &HttpConfig) -> bool {
+	http
+		.expected_status
+		.iter()
+		.flatten()
+		.any(StatusMatcher::covers_redirects)
+}
 
-	let client = shared_client();
-	let timeout = Duration::from_secs(http.timeout.unwrap_or(10));
+/// Client for `http`, trusting its `caCert` and routed through `proxy`
+pub fn client_for(
+	http: &HttpConfig,
+	proxy: Option<String>,
+) -> Result<HttpClient, Box<dyn Error + Send + Sync>> {
+	if http.client_cert.is_some() != http.client_key.is_some() {
+		return Err("clientCert and clientKey must be set together".into());
+	}
 
-	let mut request = match http.method.to_uppercase().as_str() {
-		"GET" => client.get(&http.url).timeout(timeout),
-		"POST" => client.post(&http.url).timeout(timeout),
-		"HEAD" => client.head(&http.url).timeout(timeout),
-		_ => return Err(format!("Unsupported HTTP method: {}", http.method).into()),
+	let mut resolve = BTreeMap::new();
+	for (target, address) in http.resolve.iter().flatten() {
+		let ip: IpAddr = address
+			.parse()
+			.map_err(|_| format!("Invalid resolve address for '{}': {}", target, address))?;
+		resolve.insert(target.clone(), ip);
+	}
+
+	let options = ClientOptions {
+		proxy,
+		tls: TlsSettings {
+			ca_cert: http.ca_cert.as_ref().map(PathBuf::from),
+			client_cert: http.client_cert.as_ref().map(PathBuf::from),
+			client_key: http.client_key.as_ref().map(PathBuf::from),
+			insecure_skip_verify: http.insecure_skip_verify.unwrap_or(false),
+		},
+		follow_redirects: http
+			.follow_redirects
+			.unwrap_or_else(|| !expects_redirect(http)),
+		max_redirects: http.max_redirects.unwrap_or(MAX_REDIRECTS),
+		version: http.http_version.unwrap_or_default(),
+		resolve,
 	};
+	Ok(HttpClient::cached(&options)?)
+}
 
-	if let Some(headers) = &http.headers {
-		for header in headers {
-			for (key, value) in header {
-				request = request.header(key, value);
-			}
+/// Send the request `http` describes and run its assertions and extractions.
+/// `apply` fills placeholders in header values and the body, `apply_url` in the URL.
+/// The response body is only kept when `keep_body` is set or `http` itself reads it.
+pub async fn send_checked(
+	http: &HttpConfig,
+	client: &HttpClient,
+	apply: &(dyn Fn(&str) -> String + Sync),
+	apply_url: &(dyn Fn(&str) -> String + Sync),
+	keep_body: bool,
+) -> Result<(CheckResult, Response), Box<dyn Error + Send + Sync>> {
+	let timeout = Duration::from_secs(http.timeout.unwrap_or(10));
+
+	let method = http.method.to_uppercase();
+	if !HTTP_METHODS.contains(&method.as_str()) {
+		return Err(format!("Unsupported HTTP method: {}", http.method).into());
+	}
+
+	let mut headers = HeaderMap::new();
+	for header in http.headers.iter().flatten() {
+		for (key, value) in header {
+			headers.append(
+				HeaderName::from_bytes(key.as_bytes())?,
+				HeaderValue::from_str(&apply(value))?,
+			);
 		}
 	}
 
-	let has_json_paths = http
-		.json_paths
-		.as_ref()
-		.is_some_and(|paths| !paths.is_empty());
+	if let Some(auth) = &http.auth {
+		http_auth::authorize(auth, client, timeout, &mut headers).await?;
+	}
+
+	let body = http.body.as_ref().map(|body| {
+		let (content_type, body) = body.render(apply);
+		if !headers.contains_key(CONTENT_TYPE) {
+			headers.insert(CONTENT_TYPE, HeaderValue::from_static(content_type));
+		}
+		Bytes::from(body)
+	});
+
+	let has_body_assertions =
+		http.body_contains.is_some() || http.body_not_contains.is_some() || http.body_regex.is_some();
+	let reads_body = extract::wants_body(http) || has_body_assertions;
+
+	let request = Request {
+		method: Method::from_bytes(method.as_bytes())?,
+		url: Url::parse(&apply_url(&http.url))?,
+		headers,
+		body,
+		keep_body: keep_body || reads_body,
+	};
 
 	let request_start = Instant::now();
-	let response = request.send().await?;
+	let response = tokio::time::timeout(timeout, client.send(request))
+		.await
+		.map_err(|_| "HTTP request timed out")??;
 	let request_latency = request_start.elapsed().as_secs_f64() * 1000.0;
 
-	if !response.status().is_success() {
-		return Err(format!("Request failed with status: {}", response.status()).into());
+	if response.status == StatusCode::UNAUTHORIZED
+		&& let Some(auth) = &http.auth
+	{
+		http_auth::invalidate(auth).await;
 	}
 
-	let mut result = CheckResult::new();
-	result.set("latency", request_latency);
+	if let Some(peer) = &response.peer {
+		peer.check_min_days(http.min_cert_days)?;
+	}
 
-	if has_json_paths {
-		let body = response.text().await?;
-
-		match serde_json::from_str::<serde_json::Value>(&body) {
-			Ok(json) => {
-				for (name, path) in http.json_paths.as_ref().unwrap() {
-					match extract_json_value(&json, path) {
-						Some(value) => {
-							debug!("{} = {} (path: '{}')", name, value, path);
-							result.set(name, value);
-						}
-						None => {
-							warn!(
-								"jsonPath '{}' ('{}') did not resolve to a numeric value",
-								name, path
-							);
-						}
-					}
-				}
-			}
-			Err(e) => {
-				warn!(
-					"Failed to parse HTTP response as JSON for jsonPath extraction: {}",
-					e
+	let status = response.status;
+	match http.expected_status.as_deref() {
+		Some(expected) if !expected.is_empty() => {
+			if !status_matches(expected, status.as_u16()) {
+				return Err(
+					format!(
+						"expectedStatus assertion failed: got {} (expected {})",
+						status,
+						describe_expected(expected)
+					)
+					.into(),
 				);
 			}
 		}
+		_ => {
+			if !status.is_success() {
+				return Err(format!("Request failed with status: {}", status).into());
+			}
+		}
 	}
 
+	let mut result = CheckResult::new();
+	result.set("latency", request_latency);
+	response.timings.record(&mut result);
+	result.set(
+		"httpVersion",
+		if response.version == Version::HTTP_2 {
+			2.0
+		} else {
+			1.1
+		},
+	);
+	if let Some(peer) = &response.peer {
+		peer.record(&mut result);
+	}
+	result.set("bodyBytes", response.body_bytes as f64);
+
+	check_headers(http, &response.headers)?;
+	extract::extract_headers(http, &response.headers, &mut result);
+
+	if !reads_body {
+		return Ok((result, response));
+	}
+
+	let body = String::from_utf8_lossy(&response.body);
+	check_body(http, &body)?;
+
+	extract::extract_body(http, &body, &mut result);
+
+	Ok((result, response))
+}
+
+pub async fn is_http_online(
+	monitor: &Monitor,
+) -> Result<CheckResult, Box<dyn Error + Send + Sync>> {
+	let http = monitor
+		.http
+		.as_ref()
+		.ok_or("Monitor does not contain HTTP configuration")?;
+
+	let client = client_for(http, monitor.proxy.clone())?;
+	let timestamps = timestamp_placeholders();
+	let (result, _) = send_checked(http, &client, &timestamps, &timestamps, false).await?;
 	Ok(result)
 }
 
 #[cfg(test)]
 mod tests {
 	use super::*;
+	use crate::utils::{HttpVersion, Pattern};
 	use serde_json::json;
 
 	#[test]
-	fn test_simple_object_path() {
-		let json = json!({"cryptocurrencies": {"BTC": 32.543, "ETH": 32.432}});
-		assert_eq!(
-			extract_json_value(&json, "cryptocurrencies.BTC"),
-			Some(32.543)
-		);
-		assert_eq!(
-			extract_json_value(&json, "cryptocurrencies.ETH"),
-			Some(32.432)
-		);
+	fn test_expected_status() {
+		let expected: Vec<StatusMatcher> =
+			serde_json::from_value(json!([401, "200-204", "3xx"])).unwrap();
+
+		for status in [401, 200, 204, 301, 399] {
+			assert!(status_matches(&expected, status), "{}", status);
+		}
+		for status in [205, 400, 403, 500] {
+			assert!(!status_matches(&expected, status), "{}", status);
+		}
+		assert_eq!(describe_expected(&expected), "401, 200-204, 3xx");
+
+		for invalid in ["2xx-ish", "299-200", "6xx"] {
+			let error = serde_json::from_value::<StatusMatcher>(json!(invalid)).unwrap_err();
+			assert_eq!(
+				error.to_string(),
+				format!("Invalid expectedStatus entry: '{}'", invalid)
+			);
+		}
 	}
 
 	#[test]
-	fn test_array_index_path() {
-		let json = json!({
-			"system": {
-				"cpu": [
-					{"percentage": 85.5},
-					{"percentage": 12.3}
-				]
+	fn test_expected_redirect_disables_following() {
+		let config = |extra: serde_json::Value| -> HttpConfig {
+			let mut http = json!({ "method": "GET", "url": "https://example.com" });
+			http
+				.as_object_mut()
+				.unwrap()
+				.extend(extra.as_object().unwrap().clone());
+			serde_json::from_value(http).unwrap()
+		};
+
+		assert!(!expects_redirect(&config(json!({}))));
+		assert!(!expects_redirect(&config(
+			json!({ "expectedStatus": ["2xx", 404] })
+		)));
+		assert!(expects_redirect(&config(
+			json!({ "expectedStatus": [301] })
+		)));
+		assert!(expects_redirect(&config(
+			json!({ "expectedStatus": ["200-399"] })
+		)));
+		assert!(expects_redirect(&config(
+			json!({ "expectedStatus": ["3xx"] })
+		)));
+	}
+
+	#[test]
+	fn test_header_assertions() {
+		let http: HttpConfig = serde_json::from_value(json!({
+			"method": "GET",
+			"url": "https://example.com",
+			"expectedHeaders": {
+				"Strict-Transport-Security": true,
+				"Server": false,
+				"Content-Type": "application/json",
+				"Cache-Control": { "contains": "no-store" },
+				"X-Version": { "regex": "^v\\d+$" }
 			}
-		});
+		}))
+		.unwrap();
+
+		let mut headers = HeaderMap::new();
+		headers.insert(
+			"strict-transport-security",
+			HeaderValue::from_static("max-age=63072000"),
+		);
+		headers.insert("content-type", HeaderValue::from_static("application/json"));
+		headers.append("cache-control", HeaderValue::from_static("private"));
+		headers.append("cache-control", HeaderValue::from_static("no-store"));
+		headers.insert("x-version", HeaderValue::from_static("v12"));
+		assert_eq!(check_headers(&http, &headers), Ok(()));
+
+		headers.insert("server", HeaderValue::from_static("nginx"));
 		assert_eq!(
-			extract_json_value(&json, "system.cpu.[0].percentage"),
-			Some(85.5)
+			check_headers(&http, &headers).unwrap_err(),
+			"expectedHeaders assertion failed: 'Server' present ('nginx')"
 		);
+		headers.remove("server");
+
+		headers.remove("strict-transport-security");
 		assert_eq!(
-			extract_json_value(&json, "system.cpu.[1].percentage"),
-			Some(12.3)
+			check_headers(&http, &headers).unwrap_err(),
+			"expectedHeaders assertion failed: 'Strict-Transport-Security' header missing"
+		);
+		headers.insert(
+			"strict-transport-security",
+			HeaderValue::from_static("max-age=1"),
 		);
-	}
 
-	#[test]
-	fn test_top_level_array() {
-		let json = json!([10.0, 20.0, 30.0]);
-		assert_eq!(extract_json_value(&json, "[0]"), Some(10.0));
-		assert_eq!(extract_json_value(&json, "[2]"), Some(30.0));
+		headers.insert("x-version", HeaderValue::from_static("beta"));
+		assert!(
+			check_headers(&http, &headers)
+				.unwrap_err()
+				.contains("'X-Version' is 'beta' (expected to match /^v\\d+$/)")
+		);
 	}
 
 	#[test]
-	fn test_nested_path() {
-		let json = json!({"a": {"b": {"c": {"d": 42.0}}}});
-		assert_eq!(extract_json_value(&json, "a.b.c.d"), Some(42.0));
-	}
+	fn test_body_assertions() {
+		let http = HttpConfig {
+			body_contains: Some("\"status\":\"ok\"".to_string()),
+			body_not_contains: Some("maintenance".to_string()),
+			body_regex: Some(Pattern::new(r"version: \d+\.\d+").unwrap()),
+			..Default::default()
+		};
 
-	#[test]
-	fn test_string_numeric_value() {
-		let json = json!({"value": "123.456"});
-		assert_eq!(extract_json_value(&json, "value"), Some(123.456));
-	}
+		assert!(check_body(&http, r#"{"status":"ok"} version: 3.15"#).is_ok());
 
-	#[test]
-	fn test_non_numeric_string_returns_none() {
-		let json = json!({"value": "hello"});
-		assert_eq!(extract_json_value(&json, "value"), None);
-	}
+		let err = check_body(&http, r#"{"status":"down"}"#).unwrap_err();
+		assert!(err.starts_with("bodyContains"), "{}", err);
 
-	#[test]
-	fn test_missing_path_returns_none() {
-		let json = json!({"a": {"b": 1}});
-		assert_eq!(extract_json_value(&json, "a.c"), None);
-		assert_eq!(extract_json_value(&json, "x.y.z"), None);
-	}
+		let err = check_body(&http, r#"{"status":"ok"} maintenance"#).unwrap_err();
+		assert!(err.starts_with("bodyNotContains"), "{}", err);
 
-	#[test]
-	fn test_array_out_of_bounds_returns_none() {
-		let json = json!({"items": [1, 2, 3]});
-		assert_eq!(extract_json_value(&json, "items.[99]"), None);
-	}
-
-	#[test]
-	fn test_integer_value() {
-		let json = json!({"count": 42});
-		assert_eq!(extract_json_value(&json, "count"), Some(42.0));
+		let err = check_body(&http, r#"{"status":"ok"} version: x"#).unwrap_err();
+		assert!(err.starts_with("bodyRegex"), "{}", err);
 	}
 
 	#[test]
-	fn test_boolean_value() {
-		let json = json!({"active": true});
-		assert_eq!(extract_json_value(&json, "active"), Some(1.0));
-	}
+	fn test_client_options_validation() {
+		let http: HttpConfig = serde_json::from_value(json!({
+			"method": "GET",
+			"url": "https://api.example.com/",
+			"clientCert": "/etc/pulsemonitor/client.pem"
+		}))
+		.unwrap();
+		let error = client_for(&http, None).err().unwrap();
+		assert_eq!(
+			error.to_string(),
+			"clientCert and clientKey must be set together"
+		);
 
-	#[test]
-	fn test_null_returns_none() {
-		let json = json!({"value": null});
-		assert_eq!(extract_json_value(&json, "value"), None);
+		let http: HttpConfig = serde_json::from_value(json!({
+			"method": "GET",
+			"url": "https://api.example.com/",
+			"httpVersion": "prior-knowledge",
+			"resolve": { "api.example.com:443": "10.0.0" }
+		}))
+		.unwrap();
+		assert_eq!(http.http_version, Some(HttpVersion::PriorKnowledge));
+		let error = client_for(&http, None).err().unwrap();
+		assert_eq!(
+			error.to_string(),
+			"Invalid resolve address for 'api.example.com:443': 10.0.0"
+		);
 	}
 }
@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use cookie_store::CookieStore;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+
+use crate::extract;
+use crate::services::http::{client_for, send_checked, timestamp_placeholders};
+use crate::utils::{CheckResult, Monitor};
+
+/// Everything but RFC 3986 unreserved characters, so a value stays one path segment or query value
+const URL_VALUE: &AsciiSet = &NON_ALPHANUMERIC
+	.remove(b'-')
+	.remove(b'.')
+	.remove(b'_')
+	.remove(b'~');
+
+/// Replace `{name}` with each captured variable in a single pass, so a captured value containing
+/// `{other}` is left alone. Values are percent-encoded when `encode` is set, for URLs.
+fn fill_variables(text: &str, variables: &HashMap<String, String>, encode: bool) -> String {
+	let mut out = String::with_capacity(text.len());
+	let mut rest = text;
+	while let Some(start) = rest.find('{') {
+		out.push_str(&rest[..start]);
+		let after = &rest[start + 1..];
+		let variable = after
+			.find('}')
+			.and_then(|end| Some((end, variables.get(&after[..end])?)));
+		match variable {
+			Some((end, value)) if encode => {
+				out.extend(utf8_percent_encode(value, URL_VALUE));
+				rest = &after[end + 1..];
+			}
+			Some((end, value)) => {
+				out.push_str(value);
+				rest = &after[end + 1..];
+			}
+			None => {
+				out.push('{');
+				rest = after;
+			}
+		}
+	}
+	out.push_str(rest);
+	out
+}
+
+pub async fn is_http_steps_online(
+	monitor: &Monitor,
+) -> Result<CheckResult, Box<dyn Error + Send + Sync>> {
+	let config = monitor
+		.http_steps
+		.as_ref()
+		.ok_or("Monitor does not contain httpSteps configuration")?;
+	if config.steps.is_empty() {
+		return Err("httpSteps requires at least one step".into());
+	}
+
+	let jar = Arc::new(Mutex::new(CookieStore::default()));
+	let mut variables: HashMap<String, String> = HashMap::new();
+	let mut result = CheckResult::new();
+	let start = Instant::now();
+
+	for (i, step) in config.steps.iter().enumerate() {
+		let name = step
+			.name
+			.clone()
+			.unwrap_or_else(|| format!("step{}", i + 1));
+
+		let client = client_for(&step.request, monitor.proxy.clone())?.with_cookies(jar.clone());
+		let timestamps = timestamp_placeholders();
+		let apply = |text: &str| fill_variables(&timestamps(text), &variables, false);
+		let apply_url = |text: &str| fill_variables(&timestamps(text), &variables, true);
+		let keep_body = extract::captures_body(step.captures.iter().flat_map(|c| c.values()));
+		let (step_result, response) =
+			send_checked(&step.request, &client, &apply, &apply_url, keep_body)
+				.await
+				.map_err(|e| format!("Step '{}' failed: {}", name, e))?;
+
+		for (key, value) in &step_result.values {
+			result.set(format!("{}_{}", name, key), *value);
+		}
+		for (key, value) in &step_result.labels {
+			result.set_label(format!("{}_{}", name, key), value.clone());
+		}
+
+		let body = String::from_utf8_lossy(&response.body);
+		for (variable, source) in step.captures.iter().flatten() {
+			let value = extract::capture(source, &response.headers, &body).ok_or_else(|| {
+				format!(
+					"Step '{}' failed: capture '{}' found no value",
+					name, variable
+				)
+			})?;
+			variables.insert(variable.clone(), value);
+		}
+	}
+
+	result.set("latency", start.elapsed().as_secs_f64() * 1000.0);
+	result.set("steps", config.steps.len() as f64);
+	Ok(result)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::proxy;
+	use crate::utils::HttpStepsConfig;
+	use serde_json::json;
+	use tokio::io::{AsyncReadExt, AsyncWriteExt};
+	use tokio::net::TcpListener;
+
+	fn ok(headers: &str, body: &str) -> String {
+		format!(
+			"HTTP/1.1 200 OK\r\n{}Content-Length: {}\r\n\r\n{}",
+			headers,
+			body.len(),
+			body
+		)
+	}
+
+	/// Answer one request per canned response and return the request heads
+	async fn serve(listener: TcpListener, responses: Vec<String>) -> Vec<String> {
+		let mut requests = Vec::new();
+		for response in responses {
+			let (mut socket, _) = listener.accept().await.unwrap();
+			let mut head = Vec::new();
+			while !head.ends_with(b"\r\n\r\n") {
+				head.push(socket.read_u8().await.unwrap());
+			}
+			let head = String::from_utf8(head).unwrap();
+			if let Some(length) = head
+				.lines()
+				.find_map(|line| line.strip_prefix("content-length: "))
+			{
+				let mut body = vec![0u8; length.parse().unwrap()];
+				socket.read_exact(&mut body).await.unwrap();
+			}
+			socket.write_all(response.as_bytes()).await.unwrap();
+			requests.push(head);
+		}
+		requests
+	}
+
+	#[test]
+	fn test_fill_variables() {
+		let variables = HashMap::from([
+			("id".to_string(), "a/b c".to_string()),
+			("token".to_string(), "{id}".to_string()),
+		]);
+		assert_eq!(
+			fill_variables("/items/{id}?t={token}&x={missing}", &variables, true),
+			"/items/a%2Fb%20c?t=%7Bid%7D&x={missing}"
+		);
+		// Values are inserted once, never scanned for further placeholders
+		assert_eq!(
+			fill_variables("{{token}} {token}", &variables, false),
+			"{{id}} {id}"
+		);
+	}
+
+	#[tokio::test]
+	async fn test_steps_share_variables_and_cookies() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let base = format!("http://127.0.0.1:{}", listener.local_addr().unwrap().port());
+		let server = tokio::spawn(serve(
+			listener,
+			vec![
+				ok(
+					"Set-Cookie: sid=s1; Path=/\r\n",
+					r#"{"token":"t-123","user":"u7"}"#,
+				),
+				ok("", r#"{"items":[1,2,3]}"#),
+			],
+		));
+
+		let config: HttpStepsConfig = serde_json::from_value(json!({
+			"steps": [
+				{
+					"name": "login",
+					"method": "POST",
+					"url": format!("{}/login", base),
+					"body": { "json": { "user": "probe" } },
+					"captures": { "token": { "jsonPath": "$.token" }, "user": { "jsonPath": "user" } }
+				},
+				{
+					"name": "orders",
+					"method": "GET",
+					"url": format!("{}/users/{{user}}/orders", base),
+					"headers": [{ "Authorization": "Bearer {token}" }],
+					"jsonPaths": { "count": "length($.items)" }
+				}
+			]
+		}))
+		.unwrap();
+		let monitor = Monitor {
+			name: "checkout".to_string(),
+			proxy: Some(proxy::DIRECT.to_string()),
+			http_steps: Some(config),
+			..Default::default()
+		};
+
+		let result = is_http_steps_online(&monitor).await.unwrap();
+		assert_eq!(result.get("steps"), Some(2.0));
+		assert_eq!(result.get("orders_count"), Some(3.0));
+		assert!(result.get("login_latency").is_some());
+		assert!(result.get("orders_ttfbMs").is_some());
+
+		let requests = server.await.unwrap();
+		assert!(requests[1].starts_with("GET /users/u7/orders HTTP/1.1\r\n"));
+		assert!(requests[1].contains("authorization: Bearer t-123\r\n"));
+		assert!(requests[1].contains("cookie: sid=s1\r\n"));
+	}
+
+	#[tokio::test]
+	async fn test_missing_capture_fails_step() {
+		let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+		let url = format!(
+			"http://127.0.0.1:{}/",
+			listener.local_addr().unwrap().port()
+		);
+		let server = tokio::spawn(serve(listener, vec![ok("", "{}")]));
+
+		let config: HttpStepsConfig = serde_json::from_value(json!({
+			"steps": [
+				{ "method": "GET", "url": url, "captures": { "token": { "header": "X-Token" } } },
+				{ "method": "GET", "url": url }
+			]
+		}))
+		.unwrap();
+		let monitor = Monitor {
+			proxy: Some(proxy::DIRECT.to_string()),
+			http_steps: Some(config),
+			..Default::default()
+		};
+
+		let error = is_http_steps_online(&monitor).await.unwrap_err();
+		assert_eq!(
+			error.to_string(),
+			"Step 'step1' failed: capture 'token' found no value"
+		);
+		server.await.unwrap();
+	}
+}
@@ -25,15 +25,15 @@ pub async fn is_icmp_online(
 	if output.status.success() {
 		let stdout = String::from_utf8_lossy(&output.stdout);
 
-		if let Some(rtt_line) = stdout.lines().last() {
-			if let Some(values_part) = rtt_line.split('=').nth(1) {
-				let parts: Vec<&str> = values_part.split('/').collect();
-
-				if parts.len() >= 2 {
-					if let Ok(avg) = parts[1].parse::<f64>() {
-						return Ok(CheckResult::from_latency(Some(avg)));
-					}
-				}
+		if let Some(rtt_line) = stdout.lines().last()
+			&& let Some(values_part) = rtt_line.split('=').nth(1)
+		{
+			let parts: Vec<&str> = values_part.split('/').collect();
+
+			if parts.len() >= 2
+				&& let Ok(avg) = parts[1].parse::<f64>()
+			{
+				return Ok(CheckResult::from_latency(Some(avg)));
 			}
 		}
 
@@ -1,8 +1,8 @@
+use crate::proxy::{self, Destination};
 use crate::utils::{CheckResult, Monitor};
 use std::error::Error;
 use std::time::Duration;
 use tiberius::{Client, Config};
-use tokio::net::TcpStream;
 use tokio_util::compat::TokioAsyncWriteCompatExt;
 
 pub async fn is_mssql_online(
@@ -17,9 +17,18 @@ pub async fn is_mssql_online(
 
 	let config: Config = Config::from_jdbc_string(&mssql.url)?;
 
-	let tcp = tokio::time::timeout(timeout, TcpStream::connect(config.get_addr()))
-		.await
-		.map_err(|_| "TCP connection to MSSQL timed out")??;
+	let addr = config.get_addr();
+	let (host, port) = addr
+		.rsplit_once(':')
+		.and_then(|(host, port)| Some((host, port.parse::<u16>().ok()?)))
+		.ok_or_else(|| format!("Invalid MSSQL address: {}", addr))?;
+
+	let tcp = tokio::time::timeout(
+		timeout,
+		proxy::connect_tcp(monitor.proxy.as_deref(), Destination::Tcp, host, port),
+	)
+	.await
+	.map_err(|_| "TCP connection to MSSQL timed out")??;
 
 	tcp.set_nodelay(true)?;
 
@@ -2,6 +2,7 @@ use mysql_async::{Opts, OptsBuilder, Pool, prelude::Queryable};
 use std::error::Error;
 use tokio::time::Duration;
 
+use crate::proxy::{self, Destination};
 use crate::utils::{CheckResult, Monitor};
 
 pub async fn is_mysql_online(
@@ -15,10 +16,34 @@ pub async fn is_mysql_online(
 	let timeout: Duration = Duration::from_secs(mysql.timeout.unwrap_or(3));
 
 	let opts = Opts::from_url(&mysql.url)?;
-	let builder = OptsBuilder::from_opts(opts)
+	let mut builder = OptsBuilder::from_opts(opts.clone())
 		.conn_ttl(timeout)
 		.stmt_cache_size(0);
 
+	// mysql_async opens its own sockets, so a proxied connection goes through a loopback relay.
+	// TLS still verifies the real host name.
+	let host = opts.ip_or_hostname().to_string();
+	let _tunnel = match proxy::resolve(monitor.proxy.as_deref(), Destination::Tcp, &host)? {
+		Some(route) => {
+			let tunnel =
+				tokio::time::timeout(timeout, proxy::local_tunnel(&route, &host, opts.tcp_port()))
+					.await
+					.map_err(|_| "MySQL proxy connection timed out")??;
+			builder = builder
+				.ip_or_hostname(tunnel.addr.ip().to_string())
+				.tcp_port(tunnel.addr.port())
+				.prefer_socket(false)
+				.ssl_opts(
+					opts
+						.ssl_opts()
+						.cloned()
+						.map(|ssl| ssl.with_danger_tls_hostname_override(Some(host.clone()))),
+				);
+			Some(tunnel)
+		}
+		None => None,
+	};
+
 	let pool = Pool::new(builder);
 
 	let conn_result = tokio::time::timeout(timeout, pool.get_conn()).await;
@@ -1,9 +1,46 @@
 use std::error::Error;
+use tokio::net::TcpStream;
 use tokio::time::{Duration, timeout};
-use tokio_postgres::{Client, NoTls};
+use tokio_postgres::config::Host;
+use tokio_postgres::tls::{MakeTlsConnect, TlsConnect};
+use tokio_postgres::{Client, Config, NoTls};
 
+use crate::proxy::{self, Destination};
 use crate::utils::{CheckResult, Monitor};
 
+/// Proxy plus the host and port to tunnel to
+type ProxiedTarget = (proxy::Intercept, String, u16);
+
+/// Where to tunnel when a proxy applies to this connection string
+fn proxied_target(
+	monitor: &Monitor,
+	config: &Config,
+) -> Result<Option<ProxiedTarget>, Box<dyn Error + Send + Sync>> {
+	let Some(Host::Tcp(host)) = config.get_hosts().first() else {
+		return Ok(None);
+	};
+	let port = config.get_ports().first().copied().unwrap_or(5432);
+
+	Ok(
+		proxy::resolve(monitor.proxy.as_deref(), Destination::Tcp, host)?
+			.map(|route| (route, host.clone(), port)),
+	)
+}
+
+async fn connect_raw<T>(
+	config: &Config,
+	stream: TcpStream,
+	tls: T,
+) -> Result<Client, Box<dyn Error + Send + Sync>>
+where
+	T: TlsConnect<TcpStream>,
+	T::Stream: Send + 'static,
+{
+	let (client, connection) = config.connect_raw(stream, tls).await?;
+	tokio::spawn(connection);
+	Ok(client)
+}
+
 pub async fn is_postgresql_online(
 	monitor: &Monitor,
 ) -> Result<CheckResult, Box<dyn Error + Send + Sync>> {
@@ -15,52 +52,60 @@ pub async fn is_postgresql_online(
 	let timeout_duration = Duration::from_secs(postgresql.timeout.unwrap_or(3));
 	let use_tls = postgresql.use_tls.unwrap_or(false);
 
-	if use_tls {
-		let config = rustls::ClientConfig::builder()
+	let config: Config = postgresql.url.parse()?;
+	let target = proxied_target(monitor, &config)?;
+
+	let client = if use_tls {
+		let tls_config = rustls::ClientConfig::builder()
 			.with_root_certificates(rustls::RootCertStore::empty())
 			.with_no_client_auth();
-		let connector = tokio_postgres_rustls::MakeRustlsConnect::new(config);
+		let mut connector = tokio_postgres_rustls::MakeRustlsConnect::new(tls_config);
 
-		let (client, connection): (Client, _) = match timeout(
-			timeout_duration,
-			tokio_postgres::connect(&postgresql.url, connector),
-		)
-		.await
-		{
-			Ok(Ok((client, connection))) => (client, connection),
-			Ok(Err(e)) => return Err(Box::new(e)),
-			Err(_) => return Err("PostgreSQL TLS connection timed out".into()),
+		let connect = async {
+			match &target {
+				Some((route, host, port)) => {
+					let stream = proxy::connect(Some(route), host, *port).await?;
+					let tls = MakeTlsConnect::<TcpStream>::make_tls_connect(&mut connector, host)?;
+					connect_raw(&config, stream, tls).await
+				}
+				None => {
+					let (client, connection) = tokio_postgres::connect(&postgresql.url, connector).await?;
+					tokio::spawn(connection);
+					Ok(client)
+				}
+			}
 		};
 
-		tokio::spawn(connection);
-
-		let query_result = timeout(timeout_duration, client.simple_query("SELECT 1")).await;
-
-		match query_result {
-			Ok(Ok(_)) => Ok(CheckResult::from_latency(None)),
-			Ok(Err(e)) => Err(Box::new(e)),
-			Err(_) => Err("PostgreSQL query timed out".into()),
+		match timeout(timeout_duration, connect).await {
+			Ok(client) => client?,
+			Err(_) => return Err("PostgreSQL TLS connection timed out".into()),
 		}
 	} else {
-		let (client, connection): (Client, _) = match timeout(
-			timeout_duration,
-			tokio_postgres::connect(&postgresql.url, NoTls),
-		)
-		.await
-		{
-			Ok(Ok((client, connection))) => (client, connection),
-			Ok(Err(e)) => return Err(Box::new(e)),
-			Err(_) => return Err("PostgreSQL connection timed out".into()),
+		let connect = async {
+			match &target {
+				Some((route, host, port)) => {
+					let stream = proxy::connect(Some(route), host, *port).await?;
+					connect_raw(&config, stream, NoTls).await
+				}
+				None => {
+					let (client, connection) = tokio_postgres::connect(&postgresql.url, NoTls).await?;
+					tokio::spawn(connection);
+					Ok(client)
+				}
+			}
 		};
 
-		tokio::spawn(connection);
+		match timeout(timeout_duration, connect).await {
+			Ok(client) => client?,
+			Err(_) => return Err("PostgreSQL connection timed out".into()),
+		}
+	};
 
-		let query_result = timeout(timeout_duration, client.simple_query("SELECT 1")).await;
+	let query_result = timeout(timeout_duration, client.simple_query("SELECT 1")).await;
 
-		match query_result {
-			Ok(Ok(_)) => Ok(CheckResult::from_latency(None)),
-			Ok(Err(e)) => Err(Box::new(e)),
-			Err(_) => Err("PostgreSQL query timed out".into()),
-		}
+	match query_result {
+		Ok(Ok(_)) => Ok(CheckResult::from_latency(None)),
+		Ok(Err(e)) => Err(Box::new(e)),
+		Err(_) => Err("PostgreSQL query timed out".into()),
 	}
 }
@@ -1,8 +1,13 @@
 use std::error::Error;
 use std::time::Duration;
 
-use redis::AsyncConnectionConfig;
+use redis::aio::MultiplexedConnection;
+use redis::{AsyncConnectionConfig, ConnectionAddr};
+use rustls::pki_types::ServerName;
+use tokio_rustls::TlsConnector;
 
+use crate::proxy::{self, Destination};
+use crate::tls::{self, TlsSettings};
 use crate::utils::{CheckResult, Monitor};
 
 pub async fn is_redis_online(
@@ -20,9 +25,62 @@ pub async fn is_redis_online(
 		.set_connection_timeout(Some(timeout))
 		.set_response_timeout(Some(timeout));
 
-	let mut conn = client
-		.get_multiplexed_async_connection_with_config(&config)
-		.await?;
+	let info = client.get_connection_info();
+	let proxied = match info.addr() {
+		ConnectionAddr::Tcp(host, port) => {
+			proxy::resolve(monitor.proxy.as_deref(), Destination::Tcp, host)?
+				.map(|route| (route, host.clone(), *port, None))
+		}
+		ConnectionAddr::TcpTls {
+			host,
+			port,
+			insecure,
+			..
+		} => proxy::resolve(monitor.proxy.as_deref(), Destination::Tcp, host)?
+			.map(|route| (route, host.clone(), *port, Some(*insecure))),
+		_ => None,
+	};
+
+	let mut conn = match proxied {
+		Some((route, host, port, tls)) => {
+			let stream = tokio::time::timeout(timeout, proxy::connect(Some(&route), &host, port))
+				.await
+				.map_err(|_| "Redis proxy connection timed out")??;
+			match tls {
+				// rediss:// through a proxy: the TLS session runs inside the tunnel
+				Some(insecure) => {
+					let tls = tls::check_tls(&TlsSettings {
+						insecure_skip_verify: insecure,
+						..Default::default()
+					})?;
+					let server_name = ServerName::try_from(host)?;
+					let stream = tokio::time::timeout(
+						timeout,
+						TlsConnector::from(tls.config.clone()).connect(server_name.clone(), stream),
+					)
+					.await
+					.map_err(|_| "Redis TLS handshake timed out")??;
+					let chain = stream.get_ref().1.peer_certificates().unwrap_or_default();
+					tls.verify(&tls.inspect(chain, &server_name)?)?;
+					let (conn, driver) =
+						MultiplexedConnection::new_with_config(info.redis_settings(), stream, config).await?;
+					tokio::spawn(driver);
+					conn
+				}
+				None => {
+					let (conn, driver) =
+						MultiplexedConnection::new_with_config(info.redis_settings(), stream, config).await?;
+					tokio::spawn(driver);
+					conn
+				}
+			}
+		}
+		None => {
+			client
+				.get_multiplexed_async_connection_with_config(&config)
+				.await?
+		}
+	};
 
 	let _: String = redis::cmd("PING").query_async(&mut conn).await?;
 
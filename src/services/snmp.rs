@@ -96,10 +96,9 @@ fn query_named_oids(
 		let mut retries = 3;
 		let value = loop {
 			match session.get(&oid) {
-				Ok(response) => {
+				Ok(mut response) => {
 					break response
 						.varbinds
-						.into_iter()
 						.next()
 						.and_then(|(_oid, val)| value_to_f64(&val));
 				}
@@ -210,6 +209,7 @@ fn run_snmp_v2c(
 }
 
 /// Run SNMPv3 check.
+#[allow(clippy::too_many_arguments)]
 fn run_snmp_v3(
 	addr: &str,
 	timeout: Duration,
@@ -326,9 +326,10 @@ pub async fn is_snmp_online(
 
 	let oids: HashMap<String, String> = snmp.oids.clone().unwrap_or_default();
 
+	// Credentials (community, username, passwords) are intentionally not logged
 	debug!(
-		"SNMP: connecting to {}:{} version={}, user='{}', oids={:?}",
-		host, port, version, username, oids
+		"SNMP: connecting to {}:{} version={}, securityLevel={}, oids={:?}",
+		host, port, version, security_level, oids
 	);
 
 	tokio::task::spawn_blocking(move || {
@@ -1,8 +1,14 @@
 use std::error::Error;
-use tokio::net::TcpStream;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use rustls::pki_types::ServerName;
 use tokio::time::{Duration, timeout};
+use tokio_rustls::TlsConnector;
 
-use crate::utils::{CheckResult, Monitor};
+use crate::proxy::{self, Destination};
+use crate::tls::{self, TlsSettings};
+use crate::utils::{CheckResult, Monitor, TcpConfig};
 
 pub async fn is_tcp_online(monitor: &Monitor) -> Result<CheckResult, Box<dyn Error + Send + Sync>> {
 	let tcp = monitor
@@ -10,12 +16,56 @@ pub async fn is_tcp_online(monitor: &Monitor) -> Result<CheckResult, Box<dyn Err
 		.as_ref()
 		.ok_or("Monitor does not contain TCP configuration")?;
 
-	let addr = format!("{}:{}", tcp.host, tcp.port);
 	let timeout_duration = Duration::from_secs(tcp.timeout.unwrap_or(5));
+	let proxy = proxy::resolve(monitor.proxy.as_deref(), Destination::Tcp, &tcp.host)?;
+
+	let stream = match timeout(
+		timeout_duration,
+		proxy::connect(proxy.as_ref(), &tcp.host, tcp.port),
+	)
+	.await
+	{
+		Ok(Ok(stream)) => stream,
+		Ok(Err(e)) => return Err(format!("Failed to connect to TCP server: {}", e).into()),
+		Err(_) => return Err("TCP connection attempt timed out".into()),
+	};
 
-	match timeout(timeout_duration, TcpStream::connect(&addr)).await {
-		Ok(Ok(_stream)) => Ok(CheckResult::from_latency(None)),
-		Ok(Err(e)) => Err(format!("Failed to connect to TCP server: {}", e).into()),
-		Err(_) => Err("TCP connection attempt timed out".into()),
+	if !tcp.tls.unwrap_or(false) {
+		return Ok(CheckResult::from_latency(None));
 	}
+
+	match timeout(timeout_duration, handshake(tcp, stream)).await {
+		Ok(result) => result,
+		Err(_) => Err("TLS handshake timed out".into()),
+	}
+}
+
+/// Run a TLS handshake over `stream` and report the server certificate
+async fn handshake(
+	tcp: &TcpConfig,
+	stream: tokio::net::TcpStream,
+) -> Result<CheckResult, Box<dyn Error + Send + Sync>> {
+	let tls = tls::check_tls(&TlsSettings {
+		ca_cert: tcp.ca_cert.as_ref().map(PathBuf::from),
+		insecure_skip_verify: tcp.insecure_skip_verify.unwrap_or(false),
+		..Default::default()
+	})?;
+	let server_name = ServerName::try_from(tcp.host.clone())?;
+
+	let start = Instant::now();
+	let stream = TlsConnector::from(tls.config.clone())
+		.connect(server_name.clone(), stream)
+		.await
+		.map_err(|e| format!("TLS handshake failed: {}", e))?;
+	let tls_ms = start.elapsed().as_secs_f64() * 1000.0;
+
+	let chain = stream.get_ref().1.peer_certificates().unwrap_or_default();
+	let peer = tls.inspect(chain, &server_name)?;
+	tls.verify(&peer)?;
+	peer.check_min_days(tcp.min_cert_days)?;
+
+	let mut result = CheckResult::from_latency(None);
+	result.set("tlsMs", tls_ms);
+	peer.record(&mut result);
+	Ok(result)
 }
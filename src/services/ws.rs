@@ -2,9 +2,9 @@ use futures_util::{SinkExt, StreamExt};
 use std::time::{SystemTime, UNIX_EPOCH};
 use std::{error::Error, time::Duration};
 use tokio::time::timeout;
-use tokio_tungstenite::connect_async;
 use tokio_tungstenite::tungstenite::protocol::Message;
 
+use crate::proxy;
 use crate::utils::{CheckResult, Monitor};
 
 pub async fn is_ws_online(monitor: &Monitor) -> Result<CheckResult, Box<dyn Error + Send + Sync>> {
@@ -15,7 +15,8 @@ pub async fn is_ws_online(monitor: &Monitor) -> Result<CheckResult, Box<dyn Erro
 
 	let timeout_duration = Duration::from_secs(ws.timeout.unwrap_or(3));
 
-	let (stream, _) = connect_async(&ws.url).await?;
+	let (stream, _) =
+		proxy::connect_websocket(ws.url.as_str(), monitor.proxy.as_deref(), None).await?;
 
 	let (mut write, mut read) = stream.split();
 
@@ -0,0 +1,511 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant, SystemTime};
+
+use rustls::client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier};
+use rustls::client::{verify_server_cert_signed_by_trust_anchor, verify_server_name};
+use rustls::crypto::{CryptoProvider, verify_tls12_signature, verify_tls13_signature};
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer, ServerName, UnixTime};
+use rustls::server::ParsedCertificate;
+use rustls::{ClientConfig, DigitallySignedStruct, RootCertStore, SignatureScheme};
+use tracing::warn;
+use x509_cert::Certificate;
+use x509_cert::der::Decode;
+
+use crate::utils::CheckResult;
+
+/// TLS options for connections to the server (WebSocket and HTTP fallback) or for a check
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash)]
+pub struct TlsSettings {
+	/// Extra CA certificate(s) to trust, in addition to the bundled and OS roots
+	pub ca_cert: Option<PathBuf>,
+	/// Client certificate chain for mutual TLS
+	pub client_cert: Option<PathBuf>,
+	/// Private key for `client_cert`
+	pub client_key: Option<PathBuf>,
+	/// Accept any server certificate (checks only; the chain is still inspected and reported)
+	pub insecure_skip_verify: bool,
+}
+
+/// Cached check clients unused for this long are dropped, so options removed by a reload are forgotten
+pub const CACHE_IDLE: Duration = Duration::from_secs(3600);
+
+/// Modification time and size of each configured PEM file; a rotated file changes the stamp
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FileStamp(Vec<Option<(SystemTime, u64)>>);
+
+impl TlsSettings {
+	/// Stamp of the files these settings read, to key caches so rotated certificates are reloaded
+	pub fn stamp(&self) -> FileStamp {
+		FileStamp(
+			[&self.ca_cert, &self.client_cert, &self.client_key]
+				.into_iter()
+				.flatten()
+				.map(|path| {
+					let metadata = std::fs::metadata(path).ok()?;
+					Some((metadata.modified().ok()?, metadata.len()))
+				})
+				.collect(),
+		)
+	}
+}
+
+/// Certificates from the OS trust store, loaded once per process
+fn native_roots() -> &'static [CertificateDer<'static>] {
+	static ROOTS: OnceLock<Vec<CertificateDer<'static>>> = OnceLock::new();
+	ROOTS.get_or_init(|| {
+		let result = rustls_native_certs::load_native_certs();
+		for error in &result.errors {
+			warn!("Failed to load OS root certificates: {}", error);
+		}
+		result.certs
+	})
+}
+
+fn env_path(name: &str) -> Option<PathBuf> {
+	std::env::var(name)
+		.ok()
+		.filter(|v| !v.is_empty())
+		.map(PathBuf::from)
+}
+
+fn read_pem(path: &PathBuf, what: &str) -> Result<Vec<u8>, String> {
+	std::fs::read(path).map_err(|e| format!("Failed to read {} '{}': {}", what, path.display(), e))
+}
+
+fn load_certs(path: &PathBuf, what: &str) -> Result<Vec<CertificateDer<'static>>, String> {
+	let pem = read_pem(path, what)?;
+	let certs = CertificateDer::pem_slice_iter(&pem)
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(|e| format!("Invalid {} '{}': {}", what, path.display(), e))?;
+	if certs.is_empty() {
+		return Err(format!(
+			"No certificates found in {} '{}'",
+			what,
+			path.display()
+		));
+	}
+	Ok(certs)
+}
+
+impl TlsSettings {
+	/// Read `PULSE_TLS_CA_CERT`, `PULSE_TLS_CLIENT_CERT` and `PULSE_TLS_CLIENT_KEY`.
+	pub fn from_env() -> Self {
+		TlsSettings {
+			ca_cert: env_path("PULSE_TLS_CA_CERT"),
+			client_cert: env_path("PULSE_TLS_CLIENT_CERT"),
+			client_key: env_path("PULSE_TLS_CLIENT_KEY"),
+			insecure_skip_verify: false,
+		}
+	}
+
+	/// Build a rustls config, or `None` when nothing is customized and library defaults apply.
+	pub fn client_config(&self) -> Result<Option<ClientConfig>, String> {
+		if *self == TlsSettings::default() {
+			return Ok(None);
+		}
+		self.build(false).map(|(config, _)| Some(config))
+	}
+
+	/// Build a rustls config along with the roots it trusts. With `inspect_only` the handshake
+	/// accepts any certificate, leaving the caller to judge the chain against the roots.
+	fn build(&self, inspect_only: bool) -> Result<(ClientConfig, RootCertStore), String> {
+		let mut roots = RootCertStore {
+			roots: webpki_roots::TLS_SERVER_ROOTS.to_vec(),
+		};
+		roots.add_parsable_certificates(native_roots().iter().cloned());
+		if let Some(path) = &self.ca_cert {
+			for cert in load_certs(path, "CA certificate")? {
+				roots
+					.add(cert)
+					.map_err(|e| format!("Invalid CA certificate '{}': {}", path.display(), e))?;
+			}
+		}
+
+		let provider = Arc::new(rustls::crypto::ring::default_provider());
+		let builder = ClientConfig::builder_with_provider(provider.clone())
+			.with_safe_default_protocol_versions()
+			.map_err(|e| e.to_string())?;
+		let builder = if inspect_only || self.insecure_skip_verify {
+			builder
+				.dangerous()
+				.with_custom_certificate_verifier(Arc::new(AcceptAnyCertificate(provider)))
+		} else {
+			builder.with_root_certificates(roots.clone())
+		};
+
+		let config = match (&self.client_cert, &self.client_key) {
+			(Some(cert_path), Some(key_path)) => {
+				let certs = load_certs(cert_path, "client certificate")?;
+				let key = PrivateKeyDer::from_pem_slice(&read_pem(key_path, "client key")?)
+					.map_err(|e| format!("Invalid client key '{}': {}", key_path.display(), e))?;
+				builder
+					.with_client_auth_cert(certs, key)
+					.map_err(|e| format!("Invalid client certificate/key pair: {}", e))?
+			}
+			(None, None) => builder.with_no_client_auth(),
+			_ => {
+				return Err(
+					"PULSE_TLS_CLIENT_CERT and PULSE_TLS_CLIENT_KEY must be set together".to_string(),
+				);
+			}
+		};
+
+		Ok((config, roots))
+	}
+}
+
+/// Verifier for check connections and `insecure_skip_verify`: any certificate is accepted, but
+/// handshake signatures are still checked so the connection is at least bound to the presented key.
+#[derive(Debug)]
+struct AcceptAnyCertificate(Arc<CryptoProvider>);
+
+impl ServerCertVerifier for AcceptAnyCertificate {
+	fn verify_server_cert(
+		&self,
+		_end_entity: &CertificateDer<'_>,
+		_intermediates: &[CertificateDer<'_>],
+		_server_name: &ServerName<'_>,
+		_ocsp_response: &[u8],
+		_now: UnixTime,
+	) -> Result<ServerCertVerified, rustls::Error> {
+		Ok(ServerCertVerified::assertion())
+	}
+
+	fn verify_tls12_signature(
+		&self,
+		message: &[u8],
+		cert: &CertificateDer<'_>,
+		dss: &DigitallySignedStruct,
+	) -> Result<HandshakeSignatureValid, rustls::Error> {
+		verify_tls12_signature(
+			message,
+			cert,
+			dss,
+			&self.0.signature_verification_algorithms,
+		)
+	}
+
+	fn verify_tls13_signature(
+		&self,
+		message: &[u8],
+		cert: &CertificateDer<'_>,
+		dss: &DigitallySignedStruct,
+	) -> Result<HandshakeSignatureValid, rustls::Error> {
+		verify_tls13_signature(
+			message,
+			cert,
+			dss,
+			&self.0.signature_verification_algorithms,
+		)
+	}
+
+	fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+		self.0.signature_verification_algorithms.supported_schemes()
+	}
+}
+
+/// Server connection TLS config from the environment, built once per process.
+pub fn server_tls_config() -> Result<Option<Arc<ClientConfig>>, String> {
+	static CONFIG: OnceLock<Result<Option<Arc<ClientConfig>>, String>> = OnceLock::new();
+	CONFIG
+		.get_or_init(|| {
+			TlsSettings::from_env()
+				.client_config()
+				.map(|config| config.map(Arc::new))
+		})
+		.clone()
+}
+
+/// What a TLS handshake revealed about the peer's leaf certificate
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerCertificate {
+	pub days_remaining: f64,
+	pub issuer: String,
+	pub subject: String,
+	/// Whether the certificate covers the host that was connected to (subject or SAN)
+	pub hostname_match: bool,
+	/// Whether the presented chain leads to a trusted root
+	pub chain_valid: bool,
+}
+
+impl PeerCertificate {
+	/// Store as `certDaysRemaining`, `certHostnameMatch`, `certChainValid`, `{certIssuer}` and `{certSubject}`
+	pub fn record(&self, result: &mut CheckResult) {
+		result.set("certDaysRemaining", self.days_remaining);
+		result.set(
+			"certHostnameMatch",
+			if self.hostname_match { 1.0 } else { 0.0 },
+		);
+		result.set("certChainValid", if self.chain_valid { 1.0 } else { 0.0 });
+		result.set_label("certIssuer", &self.issuer);
+		result.set_label("certSubject", &self.subject);
+	}
+
+	/// Fail when the certificate expires in fewer than `min_days` days
+	pub fn check_min_days(&self, min_days: Option<u64>) -> Result<(), String> {
+		match min_days {
+			Some(min_days) if self.days_remaining < min_days as f64 => Err(format!(
+				"Certificate for '{}' expires in {:.1} days (minCertDays {})",
+				self.subject, self.days_remaining, min_days
+			)),
+			_ => Ok(()),
+		}
+	}
+}
+
+/// TLS setup for checks: the client config plus the roots used to judge the peer's chain.
+///
+/// The handshake itself accepts any certificate so an untrusted chain or a hostname mismatch
+/// can be reported; [`CheckTls::verify`] then fails the check before any request is sent.
+#[derive(Debug)]
+pub struct CheckTls {
+	pub config: Arc<ClientConfig>,
+	roots: RootCertStore,
+	/// False with `insecure_skip_verify`
+	verify: bool,
+}
+
+impl CheckTls {
+	/// Fail unless the peer's chain is trusted and covers the host, when verification is on
+	pub fn verify(&self, peer: &PeerCertificate) -> Result<(), String> {
+		if !self.verify {
+			return Ok(());
+		}
+		if !peer.chain_valid {
+			return Err(format!(
+				"certChainValid assertion failed: certificate for '{}' issued by '{}' is not trusted",
+				peer.subject, peer.issuer
+			));
+		}
+		if !peer.hostname_match {
+			return Err(format!(
+				"certHostnameMatch assertion failed: certificate for '{}' does not cover the host",
+				peer.subject
+			));
+		}
+		Ok(())
+	}
+
+	/// Inspect the chain the peer presented during the handshake
+	pub fn inspect(
+		&self,
+		chain: &[CertificateDer<'_>],
+		server_name: &ServerName<'_>,
+	) -> Result<PeerCertificate, String> {
+		let leaf = chain.first().ok_or("Peer presented no certificate")?;
+		let parsed = ParsedCertificate::try_from(leaf).map_err(|e| e.to_string())?;
+		let algorithms = self
+			.config
+			.crypto_provider()
+			.signature_verification_algorithms
+			.all;
+		let chain_valid = verify_server_cert_signed_by_trust_anchor(
+			&parsed,
+			&self.roots,
+			&chain[1..],
+			UnixTime::now(),
+			algorithms,
+		)
+		.is_ok();
+		let hostname_match = verify_server_name(&parsed, server_name).is_ok();
+
+		let cert = Certificate::from_der(leaf).map_err(|e| format!("Invalid certificate: {}", e))?;
+		let tbs = cert.tbs_certificate;
+		let not_after = tbs.validity.not_after.to_unix_duration().as_secs_f64();
+		let now = UnixTime::now().as_secs() as f64;
+
+		Ok(PeerCertificate {
+			days_remaining: (not_after - now) / 86400.0,
+			issuer: tbs.issuer.to_string(),
+			subject: tbs.subject.to_string(),
+			hostname_match,
+			chain_valid,
+		})
+	}
+}
+
+/// Check TLS setup for `settings`, built once per distinct set of options and certificate files.
+/// Entries unused for [`CACHE_IDLE`] are dropped.
+pub fn check_tls(settings: &TlsSettings) -> Result<Arc<CheckTls>, String> {
+	type Cache = HashMap<(TlsSettings, FileStamp), (Instant, Arc<CheckTls>)>;
+	static CACHE: OnceLock<Mutex<Cache>> = OnceLock::new();
+	let mut cache = CACHE
+		.get_or_init(Default::default)
+		.lock()
+		.unwrap_or_else(|e| e.into_inner());
+	let key = (settings.clone(), settings.stamp());
+	if let Some((used, tls)) = cache.get_mut(&key) {
+		*used = Instant::now();
+		return Ok(tls.clone());
+	}
+
+	let (config, roots) = settings.build(true)?;
+	let tls = Arc::new(CheckTls {
+		config: Arc::new(config),
+		roots,
+		verify: !settings.insecure_skip_verify,
+	});
+	cache.retain(|_, (used, _)| used.elapsed() < CACHE_IDLE);
+	cache.insert(key, (Instant::now(), tls.clone()));
+	Ok(tls)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	/// Test CA and a `localhost` leaf it signed, both valid until 2126
+	const TEST_CA: &str = "-----BEGIN CERTIFICATE-----\n\
+MIIBlzCCAT2gAwIBAgIUeQcRM8Do8zTtWeJIuE3bPkTd+QgwCgYIKoZIzj0EAwIw\n\
+GDEWMBQGA1UEAwwNUHVsc2UgVGVzdCBDQTAgFw0yNjEwMTgxODQ4MjdaGA8yMTI2\n\
+MDkyNDE4NDgyN1owGDEWMBQGA1UEAwwNUHVsc2UgVGVzdCBDQTBZMBMGByqGSM49\n\
+AgEGCCqGSM49AwEHA0IABCQKX4Y3hNDLC/xsK7mVBmJWC7th7sQZ+SVr89Vb656+\n\
+/939YsAlHFKFS/nQZdbsramGAs11WgXzoTpjmjaQRU2jYzBhMB0GA1UdDgQWBBQF\n\
+zRstakY3NAywvUlHZoGwBr0v3TAfBgNVHSMEGDAWgBQFzRstakY3NAywvUlHZoGw\n\
+Br0v3TAPBgNVHRMBAf8EBTADAQH/MA4GA1UdDwEB/wQEAwICBDAKBggqhkjOPQQD\n\
+AgNIADBFAiEAqGxnKPfbDncFrmvQuPLKrMXhSn+t9W+ibErpv4njMUsCIGIgImlV\n\
+wqB8zWbCtaNRQa3mx55hx6LjQ4wXiL6XSJGy\n\
+-----END CERTIFICATE-----";
+	const TEST_LEAF: &str = "-----BEGIN CERTIFICATE-----\n\
+MIIBpzCCAU6gAwIBAgIUMgOu2kMny6FhM1RQ+DxxpzsC7NkwCgYIKoZIzj0EAwIw\n\
+GDEWMBQGA1UEAwwNUHVsc2UgVGVzdCBDQTAgFw0yNjEwMTgxODQ4MjdaGA8yMTI2\n\
+MDkyNDE4NDgyN1owFDESMBAGA1UEAwwJbG9jYWxob3N0MFkwEwYHKoZIzj0CAQYI\n\
+KoZIzj0DAQcDQgAEl9C8MGmNw8YH2q0lLJmBStAk4CRbf1E2fXRQTE9gOWxYMXlr\n\
+j+il5Q/4N9OvRelzfjunL6RI/xPvGhXso2iYt6N4MHYwFAYDVR0RBA0wC4IJbG9j\n\
+YWxob3N0MAkGA1UdEwQCMAAwEwYDVR0lBAwwCgYIKwYBBQUHAwEwHQYDVR0OBBYE\n\
+FCpczVeojYpTly6hVgS4knUPNn8oMB8GA1UdIwQYMBaAFAXNGy1qRjc0DLC9SUdm\n\
+gbAGvS/dMAoGCCqGSM49BAMCA0cAMEQCIA96q5qPQHFVbyGN4vPhYYLuS5S2ceHB\n\
+beQKiuiuVL0LAiB1FJqKOmbdPIC2xH/WZ3Br7AetE7mXAzKaYcQT/8MlcA==\n\
+-----END CERTIFICATE-----";
+
+	fn check_tls_trusting(ca: Option<&str>) -> Arc<CheckTls> {
+		let ca_cert = ca.map(|pem| {
+			let path = std::env::temp_dir().join(format!("pulse_test_root_{}.pem", std::process::id()));
+			std::fs::write(&path, pem).unwrap();
+			path
+		});
+		check_tls(&TlsSettings {
+			ca_cert,
+			..Default::default()
+		})
+		.unwrap()
+	}
+
+	#[test]
+	fn test_inspect_peer_certificate() {
+		let leaf = CertificateDer::from_pem_slice(TEST_LEAF.as_bytes()).unwrap();
+		let localhost = ServerName::try_from("localhost").unwrap();
+
+		let peer = check_tls_trusting(Some(TEST_CA))
+			.inspect(std::slice::from_ref(&leaf), &localhost)
+			.unwrap();
+		assert_eq!(peer.issuer, "CN=Pulse Test CA");
+		assert_eq!(peer.subject, "CN=localhost");
+		assert!(peer.chain_valid);
+		assert!(peer.hostname_match);
+		assert!(peer.days_remaining > 36000.0);
+		assert!(peer.check_min_days(Some(30)).is_ok());
+		assert!(
+			peer
+				.check_min_days(Some(50000))
+				.unwrap_err()
+				.contains("minCertDays 50000")
+		);
+
+		let other = ServerName::try_from("example.com").unwrap();
+		let bundled = check_tls_trusting(None);
+		let untrusted = bundled
+			.inspect(std::slice::from_ref(&leaf), &other)
+			.unwrap();
+		assert!(!untrusted.chain_valid);
+		assert!(!untrusted.hostname_match);
+		assert_eq!(
+			bundled.verify(&untrusted).unwrap_err(),
+			"certChainValid assertion failed: certificate for 'CN=localhost' issued by 'CN=Pulse Test CA' is not trusted"
+		);
+
+		let trusted = check_tls_trusting(Some(TEST_CA));
+		let wrong_host = trusted
+			.inspect(std::slice::from_ref(&leaf), &other)
+			.unwrap();
+		assert!(wrong_host.chain_valid);
+		assert!(
+			trusted
+				.verify(&wrong_host)
+				.unwrap_err()
+				.starts_with("certHostnameMatch assertion failed")
+		);
+		assert!(trusted.verify(&peer).is_ok());
+
+		let mut result = CheckResult::new();
+		peer.record(&mut result);
+		assert_eq!(result.get("certChainValid"), Some(1.0));
+		assert_eq!(result.labels["certSubject"], "CN=localhost");
+	}
+
+	#[test]
+	fn test_rotated_ca_cert_is_reloaded() {
+		let path = std::env::temp_dir().join(format!("pulse_test_rotated_{}.pem", std::process::id()));
+		let settings = TlsSettings {
+			ca_cert: Some(path.clone()),
+			..Default::default()
+		};
+
+		std::fs::write(&path, TEST_CA).unwrap();
+		let first = check_tls(&settings).unwrap();
+		assert!(Arc::ptr_eq(&first, &check_tls(&settings).unwrap()));
+
+		// A rotated file has a different size, so the next lookup reads it again
+		std::fs::write(&path, format!("{}\n{}", TEST_CA, TEST_LEAF)).unwrap();
+		let rotated = check_tls(&settings).unwrap();
+		assert!(!Arc::ptr_eq(&first, &rotated));
+		let _ = std::fs::remove_file(path);
+	}
+
+	#[test]
+	fn test_default_settings_use_library_defaults() {
+		assert!(TlsSettings::default().client_config().unwrap().is_none());
+	}
+
+	#[test]
+	fn test_invalid_settings() {
+		let cert_only = TlsSettings {
+			client_cert: Some(PathBuf::from("client.pem")),
+			..Default::default()
+		};
+		assert!(
+			cert_only
+				.client_config()
+				.unwrap_err()
+				.contains("must be set together")
+		);
+
+		let path = std::env::temp_dir().join(format!("pulse_test_ca_{}.pem", std::process::id()));
+		std::fs::write(&path, "not a certificate").unwrap();
+		let bad_ca = TlsSettings {
+			ca_cert: Some(path.clone()),
+			..Default::default()
+		};
+		assert!(
+			bad_ca
+				.client_config()
+				.unwrap_err()
+				.contains("No certificates found")
+		);
+		let _ = std::fs::remove_file(path);
+
+		let missing = TlsSettings {
+			ca_cert: Some(PathBuf::from("/nonexistent/ca.pem")),
+			..Default::default()
+		};
+		assert!(
+			missing
+				.client_config()
+				.unwrap_err()
+				.contains("Failed to read")
+		);
+	}
+}
@@ -1,27 +1,51 @@
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
 
+use regex::Regex;
+use schemars::{JsonSchema, Schema, SchemaGenerator, json_schema};
 use serde::{Deserialize, Serialize};
 
+use crate::extract::MetricSelector;
+
 pub const VERSION: &str = "v3.15.1";
 
+/// State directory when `XDG_STATE_HOME` is unset; what systemd's `StateDirectory=pulsemonitor` creates
+const DEFAULT_STATE_DIR: &str = "/var/lib/pulsemonitor";
+
+/// Directory for files kept across restarts, such as the instance ID:
+/// `$XDG_STATE_HOME/pulsemonitor`, otherwise `/var/lib/pulsemonitor`
+pub fn state_dir() -> PathBuf {
+	state_dir_from(std::env::var_os("XDG_STATE_HOME"))
+}
+
+fn state_dir_from(xdg_state_home: Option<OsString>) -> PathBuf {
+	// The XDG spec says relative paths are invalid and must be ignored
+	xdg_state_home
+		.filter(|dir| Path::new(dir).is_absolute())
+		.map(|dir| PathBuf::from(dir).join("pulsemonitor"))
+		.unwrap_or_else(|| PathBuf::from(DEFAULT_STATE_DIR))
+}
+
 #[derive(Default, Debug, Clone)]
 pub struct CheckResult {
 	pub values: HashMap<String, f64>,
+	/// Text results (e.g. certificate issuer); usable as placeholders but never pushed as metrics
+	pub labels: HashMap<String, String>,
 }
 
 impl CheckResult {
 	pub fn new() -> Self {
-		CheckResult {
-			values: HashMap::new(),
-		}
+		CheckResult::default()
 	}
 
 	pub fn from_latency(latency: Option<f64>) -> Self {
-		let mut values = HashMap::new();
+		let mut result = CheckResult::new();
 		if let Some(l) = latency {
-			values.insert("latency".to_string(), l);
+			result.set("latency", l);
 		}
-		CheckResult { values }
+		result
 	}
 
 	pub fn latency(&self) -> Option<f64> {
@@ -35,6 +59,51 @@ impl CheckResult {
 	pub fn get(&self, key: &str) -> Option<f64> {
 		self.values.get(key).copied()
 	}
+
+	pub fn set_label(&mut self, key: impl Into<String>, value: impl Into<String>) {
+		self.labels.insert(key.into(), value.into());
+	}
+}
+
+/// A heartbeat slot that `customMetrics` can fill; other keys are rejected when the config is loaded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CustomSlot {
+	Custom1,
+	Custom2,
+	Custom3,
+}
+
+impl CustomSlot {
+	pub const ALL: [CustomSlot; 3] = [
+		CustomSlot::Custom1,
+		CustomSlot::Custom2,
+		CustomSlot::Custom3,
+	];
+
+	pub fn key(self) -> &'static str {
+		match self {
+			CustomSlot::Custom1 => "custom1",
+			CustomSlot::Custom2 => "custom2",
+			CustomSlot::Custom3 => "custom3",
+		}
+	}
+}
+
+/// Resolve the value for a `custom1`..`custom3` slot, honoring the monitor's `customMetrics` mapping.
+pub fn resolve_custom_metric(
+	monitor: &Monitor,
+	result: &CheckResult,
+	slot: CustomSlot,
+) -> Option<f64> {
+	match monitor
+		.custom_metrics
+		.as_ref()
+		.and_then(|mapping| mapping.get(&slot))
+	{
+		Some(key) => result.get(key),
+		None => result.get(slot.key()),
+	}
 }
 
 pub fn resolve_custom_placeholders(
@@ -44,9 +113,11 @@ pub fn resolve_custom_placeholders(
 	let mut placeholders = Vec::new();
 
 	// Always emit {custom1}, {custom2}, {custom3} (empty string if absent)
-	for key in &["custom1", "custom2", "custom3"] {
-		let value_str = result.get(key).map(|v| v.to_string()).unwrap_or_default();
-		placeholders.push((format!("{{{}}}", key), value_str));
+	for slot in CustomSlot::ALL {
+		let value_str = resolve_custom_metric(monitor, result, slot)
+			.map(|v| v.to_string())
+			.unwrap_or_default();
+		placeholders.push((format!("{{{}}}", slot.key()), value_str));
 	}
 
 	// Minecraft alias
@@ -69,17 +140,21 @@ pub fn resolve_custom_placeholders(
 		}
 	}
 
+	for (key, value) in &result.labels {
+		placeholders.push((format!("{{{}}}", key), value.clone()));
+	}
+
 	placeholders
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
 	pub monitors: Vec<Monitor>,
 	pub max_concurrent_checks: Option<usize>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct Monitor {
 	pub enabled: bool,
@@ -90,7 +165,17 @@ pub struct Monitor {
 	/// Heartbeat configuration (used in file mode, optional in WebSocket mode)
 	pub heartbeat: Option<HeartbeatConfig>,
 	pub debug: Option<bool>,
+	/// Map of custom1/custom2/custom3 -> result key used to populate that slot
+	pub custom_metrics: Option<HashMap<CustomSlot, String>>,
+	/// Where this monitor came from (e.g. "local" in hybrid mode); namespaces its scheduler key.
+	/// Set at runtime, never read from or written to config.
+	#[serde(skip)]
+	pub origin: Option<String>,
+	/// Proxy for this monitor's check (`http://`, `socks5://`, `socks5h://` or `direct`).
+	/// Overrides `HTTPS_PROXY` / `ALL_PROXY` / `NO_PROXY`.
+	pub proxy: Option<String>,
 	pub http: Option<HttpConfig>,
+	pub http_steps: Option<HttpStepsConfig>,
 	pub ws: Option<WsConfig>,
 	pub tcp: Option<TcpConfig>,
 	pub udp: Option<UdpConfig>,
@@ -106,32 +191,93 @@ pub struct Monitor {
 	#[serde(rename = "minecraft-bedrock")]
 	pub minecraft_bedrock: Option<MinecraftBedrockConfig>,
 	pub snmp: Option<SnmpConfig>,
+	pub composite: Option<CompositeConfig>,
+}
+
+/// Service types a monitor can use, named after their config keys
+pub const SERVICE_TYPES: &[&str] = &[
+	"http",
+	"httpSteps",
+	"ws",
+	"tcp",
+	"udp",
+	"icmp",
+	"smtp",
+	"imap",
+	"mysql",
+	"mssql",
+	"postgresql",
+	"redis",
+	"minecraft-java",
+	"minecraft-bedrock",
+	"snmp",
+	"composite",
+];
+
+impl Monitor {
+	/// The service this monitor checks, in the same precedence used when running it
+	pub fn service_type(&self) -> Option<&'static str> {
+		self.service_types().into_iter().next()
+	}
+
+	/// Every service block set on this monitor, in precedence order
+	pub fn service_types(&self) -> Vec<&'static str> {
+		let configured = [
+			self.http.is_some(),
+			self.http_steps.is_some(),
+			self.ws.is_some(),
+			self.tcp.is_some(),
+			self.udp.is_some(),
+			self.icmp.is_some(),
+			self.smtp.is_some(),
+			self.imap.is_some(),
+			self.mysql.is_some(),
+			self.mssql.is_some(),
+			self.postgresql.is_some(),
+			self.redis.is_some(),
+			self.minecraft_java.is_some(),
+			self.minecraft_bedrock.is_some(),
+			self.snmp.is_some(),
+			self.composite.is_some(),
+		];
+		configured
+			.iter()
+			.zip(SERVICE_TYPES)
+			.filter(|(set, _)| **set)
+			.map(|(_, service)| *service)
+			.collect()
+	}
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct HeartbeatConfig {
+	#[schemars(extend("pattern" = HTTP_METHOD_PATTERN))]
 	pub method: String,
 	pub url: String,
 	pub timeout: Option<u64>,
 	pub headers: Option<Vec<HashMap<String, String>>>,
+	/// Request body; supports the same placeholders as `url`
+	pub body: Option<RequestBody>,
+	/// Proxy for the heartbeat request, same format as the monitor `proxy`
+	pub proxy: Option<String>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct MysqlConfig {
 	pub url: String,
 	pub timeout: Option<u64>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct MssqlConfig {
 	pub url: String,
 	pub timeout: Option<u64>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct PostgreSqlConfig {
 	pub url: String,
@@ -139,39 +285,410 @@ pub struct PostgreSqlConfig {
 	pub use_tls: Option<bool>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct RedisConfig {
 	pub url: String,
 	pub timeout: Option<u64>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct HttpConfig {
+	#[schemars(extend("pattern" = HTTP_METHOD_PATTERN))]
 	pub method: String,
 	pub url: String,
 	pub timeout: Option<u64>,
 	pub headers: Option<Vec<HashMap<String, String>>>,
+	/// Request body; supports `{timestampISO}` and `{timestampUnix}`
+	pub body: Option<RequestBody>,
 	pub json_paths: Option<HashMap<String, String>>,
+	/// Map of placeholder name -> regular expression whose capture group holds the value
+	pub regex_paths: Option<HashMap<String, Pattern>>,
+	/// Map of placeholder name -> XPath expression evaluated against an XML body
+	pub xpath_paths: Option<HashMap<String, String>>,
+	/// Map of placeholder name -> Prometheus metric selector, e.g. `up{job="api"}`
+	pub prometheus_metrics: Option<HashMap<String, MetricSelector>>,
+	/// Accepted status codes; any 2xx when unset
+	pub expected_status: Option<Vec<StatusMatcher>>,
+	/// Text the response body must contain
+	pub body_contains: Option<String>,
+	/// Text the response body must not contain
+	pub body_not_contains: Option<String>,
+	/// Regular expression the response body must match
+	pub body_regex: Option<Pattern>,
+	/// Map of placeholder name -> response header holding a number, e.g. `X-Queue-Depth`
+	pub header_metrics: Option<HashMap<String, String>>,
+	/// Response headers that must (or must not) be present, keyed by header name
+	pub expected_headers: Option<HashMap<String, HeaderAssertion>>,
+	/// PEM file with extra CA certificates to trust (e.g. for self-signed servers)
+	pub ca_cert: Option<String>,
+	/// Fail when the server certificate expires in fewer days than this
+	pub min_cert_days: Option<u64>,
+	/// Credentials sent as the `Authorization` header
+	pub auth: Option<HttpAuth>,
+	/// Follow redirects (default true); when false the 3xx response itself is checked
+	pub follow_redirects: Option<bool>,
+	/// Redirects followed before the check fails (default 10)
+	pub max_redirects: Option<usize>,
+	pub http_version: Option<HttpVersion>,
+	/// Accept any server certificate; certificate metrics are still recorded
+	pub insecure_skip_verify: Option<bool>,
+	/// PEM client certificate for mutual TLS; requires `clientKey`
+	pub client_cert: Option<String>,
+	/// PEM private key for `clientCert`
+	pub client_key: Option<String>,
+	/// Map of `host:port` (or `host`) -> IP address to connect to instead of resolving, like curl's `--resolve`
+	pub resolve: Option<HashMap<String, String>>,
+}
+
+/// Protocol for HTTP checks
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "kebab-case")]
+pub enum HttpVersion {
+	/// HTTP/1.1 only
+	#[default]
+	Http1,
+	/// HTTP/2 when the server offers it via ALPN, otherwise HTTP/1.1
+	Http2,
+	/// HTTP/2 without negotiation, including cleartext h2c for `http://` URLs
+	PriorKnowledge,
+}
+
+/// Credentials for HTTP checks, selected by `type`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(
+	tag = "type",
+	rename_all = "lowercase",
+	rename_all_fields = "camelCase"
+)]
+pub enum HttpAuth {
+	/// HTTP Basic authentication
+	Basic { username: String, password: String },
+	/// Bearer token read from an environment variable on every request
+	Bearer { token_env: String },
+	/// OAuth2 client credentials grant; tokens are cached until they expire
+	#[serde(rename = "oauth2")]
+	OAuth2(OAuth2Config),
+}
+
+/// OAuth2 client credentials; monitors with identical settings share one cached token
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct OAuth2Config {
+	pub token_url: String,
+	pub client_id: String,
+	pub client_secret: String,
+	pub scope: Option<String>,
+	/// Sent as `audience`, as some providers (e.g. Auth0) require
+	pub audience: Option<String>,
+}
+
+/// HTTP methods accepted by `HttpConfig` and `HeartbeatConfig`
+pub const HTTP_METHODS: &[&str] = &["GET", "POST", "PUT", "PATCH", "DELETE", "HEAD", "OPTIONS"];
+
+/// Schema for [`HTTP_METHODS`] in any case, as the method is uppercased before use
+const HTTP_METHOD_PATTERN: &str = "^([Gg][Ee][Tt]|[Pp][Oo][Ss][Tt]|[Pp][Uu][Tt]|[Pp][Aa][Tt][Cc][Hh]|[Dd][Ee][Ll][Ee][Tt][Ee]|[Hh][Ee][Aa][Dd]|[Oo][Pp][Tt][Ii][Oo][Nn][Ss])$";
+
+/// Request body: a raw string, `{ json = ... }` or `{ form = { ... } }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum RequestBody {
+	Raw(String),
+	Json { json: serde_json::Value },
+	Form { form: HashMap<String, String> },
+}
+
+impl RequestBody {
+	/// Render the body with `apply` filling in placeholders; returns the default content type and the body.
+	pub fn render(&self, apply: impl Fn(&str) -> String) -> (&'static str, String) {
+		match self {
+			RequestBody::Raw(text) => ("text/plain; charset=utf-8", apply(text)),
+			RequestBody::Json { json } => ("application/json", render_json(json, &apply).to_string()),
+			RequestBody::Form { form } => {
+				let mut fields: Vec<_> = form.iter().collect();
+				fields.sort();
+				let body = form_urlencoded::Serializer::new(String::new())
+					.extend_pairs(fields.into_iter().map(|(key, value)| (key, apply(value))))
+					.finish();
+				("application/x-www-form-urlencoded", body)
+			}
+		}
+	}
+}
+
+/// Fill placeholders in every string value of a JSON body
+fn render_json(value: &serde_json::Value, apply: &impl Fn(&str) -> String) -> serde_json::Value {
+	match value {
+		serde_json::Value::String(text) => serde_json::Value::String(apply(text)),
+		serde_json::Value::Array(items) => {
+			serde_json::Value::Array(items.iter().map(|item| render_json(item, apply)).collect())
+		}
+		serde_json::Value::Object(fields) => serde_json::Value::Object(
+			fields
+				.iter()
+				.map(|(key, value)| (key.clone(), render_json(value, apply)))
+				.collect(),
+		),
+		other => other.clone(),
+	}
+}
+
+/// Whether a `headers` list sets `name` (case-insensitive)
+pub fn has_header(headers: Option<&Vec<HashMap<String, String>>>, name: &str) -> bool {
+	headers
+		.into_iter()
+		.flatten()
+		.any(|header| header.keys().any(|key| key.eq_ignore_ascii_case(name)))
+}
+
+/// Multi-step HTTP transaction: requests run in order and share a cookie jar
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpStepsConfig {
+	pub steps: Vec<HttpStep>,
+}
+
+/// One request of an `httpSteps` monitor
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct HttpStep {
+	/// Prefix for this step's metrics (default: "step1", "step2", ...)
+	pub name: Option<String>,
+	#[serde(flatten)]
+	pub request: HttpConfig,
+	/// Map of variable name -> where to capture it from; later steps use it as `{name}`
+	pub captures: Option<HashMap<String, Capture>>,
+}
+
+/// Where a step variable is captured from
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum Capture {
+	/// JSON path into the response body, same syntax as `jsonPaths`
+	JsonPath(String),
+	/// Response header value
+	Header(String),
+	/// Regular expression over the response body, same rules as `regexPaths`
+	Regex(Pattern),
+}
+
+/// Expected response header: `true`/`false` for presence/absence, a string for an exact value,
+/// or `{ contains = "..." }` / `{ regex = "..." }`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub enum HeaderAssertion {
+	Present(bool),
+	Equals(String),
+	Matches {
+		contains: Option<String>,
+		regex: Option<Pattern>,
+	},
+}
+
+/// An accepted HTTP status: a single code, or a range like `"200-299"` or `"3xx"`
+#[derive(Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum StatusMatcher {
+	Code(u16),
+	Range(StatusRange),
+}
+
+impl<'de> Deserialize<'de> for StatusMatcher {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		// Parsed by hand so an invalid range reports itself rather than "did not match any variant"
+		#[derive(Deserialize)]
+		#[serde(untagged)]
+		enum Raw {
+			Code(u16),
+			Range(String),
+		}
+		match Raw::deserialize(deserializer)? {
+			Raw::Code(code) => Ok(StatusMatcher::Code(code)),
+			Raw::Range(text) => StatusRange::try_from(text)
+				.map(StatusMatcher::Range)
+				.map_err(serde::de::Error::custom),
+		}
+	}
+}
+
+impl StatusMatcher {
+	pub fn matches(&self, status: u16) -> bool {
+		match self {
+			StatusMatcher::Code(code) => *code == status,
+			StatusMatcher::Range(range) => (range.low..=range.high).contains(&status),
+		}
+	}
+
+	/// Whether this accepts any redirect status
+	pub fn covers_redirects(&self) -> bool {
+		match self {
+			StatusMatcher::Code(code) => (300..400).contains(code),
+			StatusMatcher::Range(range) => range.low < 400 && range.high >= 300,
+		}
+	}
+}
+
+impl std::fmt::Display for StatusMatcher {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			StatusMatcher::Code(code) => write!(f, "{}", code),
+			StatusMatcher::Range(range) => f.write_str(&range.text),
+		}
+	}
+}
+
+/// Inclusive status range, validated when the config is parsed
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(into = "String")]
+pub struct StatusRange {
+	text: String,
+	low: u16,
+	high: u16,
+}
+
+impl TryFrom<String> for StatusRange {
+	type Error = String;
+
+	fn try_from(text: String) -> Result<Self, String> {
+		let range = text.trim();
+		let invalid = || format!("Invalid expectedStatus entry: '{}'", range);
+		let code = |s: &str| s.trim().parse::<u16>().map_err(|_| invalid());
+
+		let (low, high) = if let Some(class) = range
+			.strip_suffix("xx")
+			.or_else(|| range.strip_suffix("XX"))
+		{
+			let class = code(class)?;
+			if !(1..=5).contains(&class) {
+				return Err(invalid());
+			}
+			(class * 100, class * 100 + 99)
+		} else {
+			match range.split_once('-') {
+				Some((low, high)) => {
+					let (low, high) = (code(low)?, code(high)?);
+					if low > high {
+						return Err(invalid());
+					}
+					(low, high)
+				}
+				None => {
+					let code = code(range)?;
+					(code, code)
+				}
+			}
+		};
+
+		Ok(StatusRange { text, low, high })
+	}
+}
+
+impl From<StatusRange> for String {
+	fn from(range: StatusRange) -> String {
+		range.text
+	}
+}
+
+impl JsonSchema for StatusRange {
+	fn inline_schema() -> bool {
+		true
+	}
+
+	fn schema_name() -> Cow<'static, str> {
+		"StatusRange".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"type": "string",
+			"pattern": "^\\s*([1-5][xX][xX]|[0-9]+(\\s*-\\s*[0-9]+)?)\\s*$"
+		})
+	}
+}
+
+/// Regular expression from the config, compiled when the config is parsed
+#[derive(Debug, Clone)]
+pub struct Pattern(Regex);
+
+impl Pattern {
+	pub fn new(pattern: &str) -> Result<Self, regex::Error> {
+		Regex::new(pattern).map(Pattern)
+	}
+}
+
+impl std::ops::Deref for Pattern {
+	type Target = Regex;
+
+	fn deref(&self) -> &Regex {
+		&self.0
+	}
+}
+
+impl std::fmt::Display for Pattern {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		f.write_str(self.0.as_str())
+	}
+}
+
+impl PartialEq for Pattern {
+	fn eq(&self, other: &Self) -> bool {
+		self.0.as_str() == other.0.as_str()
+	}
+}
+
+impl Serialize for Pattern {
+	fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		serializer.serialize_str(self.0.as_str())
+	}
+}
+
+impl<'de> Deserialize<'de> for Pattern {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let pattern = String::deserialize(deserializer)?;
+		Pattern::new(&pattern)
+			.map_err(|e| serde::de::Error::custom(format!("Invalid regex '{}': {}", pattern, e)))
+	}
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+impl JsonSchema for Pattern {
+	fn inline_schema() -> bool {
+		true
+	}
+
+	fn schema_name() -> Cow<'static, str> {
+		"Pattern".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator) -> Schema {
+		json_schema!({ "type": "string", "format": "regex" })
+	}
+}
+
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct WsConfig {
 	pub url: String,
 	pub timeout: Option<u64>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct TcpConfig {
 	pub host: String,
 	pub port: u16,
 	pub timeout: Option<u64>,
+	/// Perform a TLS handshake after connecting and report certificate metrics
+	pub tls: Option<bool>,
+	/// PEM file with extra CA certificates to trust (e.g. for self-signed servers)
+	pub ca_cert: Option<String>,
+	/// Fail when the server certificate expires in fewer days than this
+	pub min_cert_days: Option<u64>,
+	/// Accept any certificate (with `tls`); certificate metrics are still recorded
+	pub insecure_skip_verify: Option<bool>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct UdpConfig {
 	pub host: String,
@@ -181,14 +698,14 @@ pub struct UdpConfig {
 	pub expect_response: Option<bool>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct IcmpConfig {
 	pub host: String,
 	pub timeout: Option<u64>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct ImapConfig {
 	pub server: String,
@@ -197,13 +714,13 @@ pub struct ImapConfig {
 	pub password: String,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SmtpConfig {
 	pub url: String,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct MinecraftJavaConfig {
 	pub host: String,
@@ -211,7 +728,7 @@ pub struct MinecraftJavaConfig {
 	pub timeout: Option<u64>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct MinecraftBedrockConfig {
 	pub host: String,
@@ -219,13 +736,14 @@ pub struct MinecraftBedrockConfig {
 	pub timeout: Option<u64>,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
 #[serde(rename_all = "camelCase")]
 pub struct SnmpConfig {
 	pub host: String,
 	pub port: Option<u16>,
 	pub timeout: Option<u64>,
 	/// SNMP version: "1", "2c", or "3" (default: "3")
+	#[schemars(extend("enum" = ["1", "v1", "2", "2c", "v2", "v2c", "3", "v3"]))]
 	pub version: Option<String>,
 	/// Community string for v1/v2c (default: "public")
 	pub community: Option<String>,
@@ -234,12 +752,15 @@ pub struct SnmpConfig {
 	/// SNMPv3 authentication password
 	pub auth_password: Option<String>,
 	/// SNMPv3 auth protocol: md5, sha1, sha256, etc. (default: "sha256")
+	#[schemars(extend("enum" = ["md5", "sha", "sha1", "sha-1", "sha224", "sha-224", "sha256", "sha-256", "sha384", "sha-384", "sha512", "sha-512"]))]
 	pub auth_protocol: Option<String>,
 	/// SNMPv3 privacy password (required for authPriv)
 	pub priv_password: Option<String>,
 	/// SNMPv3 privacy cipher: des, aes128, aes192, aes256 (default: "aes128")
+	#[schemars(extend("enum" = ["des", "aes", "aes128", "aes-128", "aes192", "aes-192", "aes256", "aes-256"]))]
 	pub priv_cipher: Option<String>,
 	/// SNMPv3 security level: noAuthNoPriv, authNoPriv, authPriv (default: "authPriv")
+	#[schemars(extend("enum" = ["noAuthNoPriv", "authNoPriv", "authPriv"]))]
 	pub security_level: Option<String>,
 	/// Primary OID for availability check (default: sysUpTime 1.3.6.1.2.1.1.3.0)
 	pub oid: Option<String>,
@@ -247,6 +768,198 @@ pub struct SnmpConfig {
 	pub oids: Option<HashMap<String, String>>,
 }
 
+#[derive(Default, Debug, Clone, PartialEq, Serialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CompositeConfig {
+	/// Success policy: "all", "any", or "quorum(n)" in any case (default: "all")
+	pub policy: Option<CompositePolicy>,
+	/// Sub-checks run concurrently on every interval
+	pub checks: Vec<CompositeCheck>,
+}
+
+impl<'de> Deserialize<'de> for CompositeConfig {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		// Validated here so a broken composite fails to load instead of failing every interval
+		#[derive(Deserialize)]
+		struct Raw {
+			policy: Option<CompositePolicy>,
+			checks: Vec<CompositeCheck>,
+		}
+		let Raw { policy, checks } = Raw::deserialize(deserializer)?;
+
+		if checks.is_empty() {
+			return Err(serde::de::Error::custom(
+				"Composite monitor has no sub-checks",
+			));
+		}
+		if let Some(CompositePolicy::Quorum(n)) = policy
+			&& n > checks.len()
+		{
+			return Err(serde::de::Error::custom(format!(
+				"Composite policy 'quorum({})' can never pass with {} sub-checks",
+				n,
+				checks.len()
+			)));
+		}
+		for (i, check) in checks.iter().enumerate() {
+			check
+				.to_monitor(&Monitor::default(), &check.name(i))
+				.map_err(serde::de::Error::custom)?;
+		}
+
+		Ok(CompositeConfig { policy, checks })
+	}
+}
+
+/// How many composite sub-checks must succeed
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(into = "String")]
+pub enum CompositePolicy {
+	#[default]
+	All,
+	Any,
+	/// At least this many, never zero
+	Quorum(usize),
+}
+
+impl<'de> Deserialize<'de> for CompositePolicy {
+	fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let policy = String::deserialize(deserializer)?;
+		let normalized = policy.trim().to_lowercase();
+		match normalized.as_str() {
+			"all" => Ok(CompositePolicy::All),
+			"any" => Ok(CompositePolicy::Any),
+			_ => normalized
+				.strip_prefix("quorum(")
+				.and_then(|s| s.strip_suffix(')'))
+				.and_then(|n| n.trim().parse::<usize>().ok())
+				.filter(|n| *n > 0)
+				.map(CompositePolicy::Quorum)
+				.ok_or_else(|| {
+					serde::de::Error::custom(format!(
+						"Unsupported composite policy '{}'. Supported: all, any, quorum(n)",
+						policy
+					))
+				}),
+		}
+	}
+}
+
+impl std::fmt::Display for CompositePolicy {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		match self {
+			CompositePolicy::All => f.write_str("all"),
+			CompositePolicy::Any => f.write_str("any"),
+			CompositePolicy::Quorum(n) => write!(f, "quorum({})", n),
+		}
+	}
+}
+
+impl From<CompositePolicy> for String {
+	fn from(policy: CompositePolicy) -> String {
+		policy.to_string()
+	}
+}
+
+impl JsonSchema for CompositePolicy {
+	fn inline_schema() -> bool {
+		true
+	}
+
+	fn schema_name() -> Cow<'static, str> {
+		"CompositePolicy".into()
+	}
+
+	fn json_schema(_: &mut SchemaGenerator) -> Schema {
+		json_schema!({
+			"type": "string",
+			"pattern": "^\\s*([Aa][Ll][Ll]|[Aa][Nn][Yy]|[Qq][Uu][Oo][Rr][Uu][Mm]\\(\\s*0*[1-9][0-9]*\\s*\\))\\s*$"
+		})
+	}
+}
+
+/// A single sub-check of a composite monitor; holds exactly one service block.
+#[derive(Default, Debug, Clone, PartialEq, Serialize, Deserialize, JsonSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CompositeCheck {
+	/// Prefix for this sub-check's metrics (default: "check1", "check2", ...)
+	pub name: Option<String>,
+	pub http: Option<HttpConfig>,
+	pub http_steps: Option<HttpStepsConfig>,
+	pub ws: Option<WsConfig>,
+	pub tcp: Option<TcpConfig>,
+	pub udp: Option<UdpConfig>,
+	pub icmp: Option<IcmpConfig>,
+	pub smtp: Option<SmtpConfig>,
+	pub imap: Option<ImapConfig>,
+	pub mysql: Option<MysqlConfig>,
+	pub mssql: Option<MssqlConfig>,
+	pub postgresql: Option<PostgreSqlConfig>,
+	pub redis: Option<RedisConfig>,
+	#[serde(rename = "minecraft-java")]
+	pub minecraft_java: Option<MinecraftJavaConfig>,
+	#[serde(rename = "minecraft-bedrock")]
+	pub minecraft_bedrock: Option<MinecraftBedrockConfig>,
+	pub snmp: Option<SnmpConfig>,
+}
+
+impl CompositeCheck {
+	/// Prefix for this sub-check's metrics; `index` is its position in `checks`
+	pub fn name(&self, index: usize) -> String {
+		self
+			.name
+			.clone()
+			.unwrap_or_else(|| format!("check{}", index + 1))
+	}
+
+	/// Build a standalone monitor for this sub-check so it can run through the regular service dispatch.
+	/// Fails unless exactly one service block is set.
+	pub fn to_monitor(&self, parent: &Monitor, name: &str) -> Result<Monitor, String> {
+		let monitor = Monitor {
+			enabled: true,
+			interval: parent.interval,
+			name: format!("{}/{}", parent.name, name),
+			debug: parent.debug,
+			proxy: parent.proxy.clone(),
+			http: self.http.clone(),
+			http_steps: self.http_steps.clone(),
+			ws: self.ws.clone(),
+			tcp: self.tcp.clone(),
+			udp: self.udp.clone(),
+			icmp: self.icmp.clone(),
+			smtp: self.smtp.clone(),
+			imap: self.imap.clone(),
+			mysql: self.mysql.clone(),
+			mssql: self.mssql.clone(),
+			postgresql: self.postgresql.clone(),
+			redis: self.redis.clone(),
+			minecraft_java: self.minecraft_java.clone(),
+			minecraft_bedrock: self.minecraft_bedrock.clone(),
+			snmp: self.snmp.clone(),
+			..Default::default()
+		};
+
+		match monitor.service_types().as_slice() {
+			[_] => Ok(monitor),
+			[] => Err(format!(
+				"Composite sub-check '{}' has no service configuration. Supported: {}",
+				name,
+				SERVICE_TYPES
+					.iter()
+					.filter(|service| **service != "composite")
+					.copied()
+					.collect::<Vec<_>>()
+					.join(", ")
+			)),
+			services => Err(format!(
+				"Composite sub-check '{}' must have exactly one service, found: {}",
+				name,
+				services.join(", ")
+			)),
+		}
+	}
+}
+
 // WebSocket Protocol Messages
 
 /// Data wrapper for monitor list
@@ -298,10 +1011,10 @@ impl PushMessage {
 		}
 	}
 
-	pub fn with_custom_metrics(mut self, result: &CheckResult) -> Self {
-		self.custom1 = result.get("custom1");
-		self.custom2 = result.get("custom2");
-		self.custom3 = result.get("custom3");
+	pub fn with_custom_metrics(mut self, monitor: &Monitor, result: &CheckResult) -> Self {
+		self.custom1 = resolve_custom_metric(monitor, result, CustomSlot::Custom1);
+		self.custom2 = resolve_custom_metric(monitor, result, CustomSlot::Custom2);
+		self.custom3 = resolve_custom_metric(monitor, result, CustomSlot::Custom3);
 		self
 	}
 }
@@ -315,6 +1028,9 @@ pub enum WsMessage {
 	},
 	Subscribe {
 		token: String,
+		/// Describes this agent to the server
+		#[serde(skip_serializing_if = "Option::is_none", default)]
+		agent: Option<AgentHello>,
 	},
 	Subscribed {
 		#[serde(rename = "pulseMonitorId")]
@@ -322,6 +1038,9 @@ pub enum WsMessage {
 		#[serde(rename = "pulseMonitorName")]
 		pulse_monitor_name: String,
 		data: MonitorData,
+		/// Optional protocol features the server supports
+		#[serde(default)]
+		capabilities: Vec<String>,
 		timestamp: String,
 	},
 	Error {
@@ -340,12 +1059,304 @@ pub enum WsMessage {
 		monitor_id: String,
 		timestamp: String,
 	},
+	/// Run a monitor's check immediately
+	RunNow {
+		token: String,
+		#[serde(rename = "commandId", default)]
+		command_id: Option<String>,
+	},
+	/// Stop running a monitor's checks, optionally until an RFC 3339 timestamp
+	Pause {
+		token: String,
+		#[serde(default)]
+		until: Option<String>,
+		#[serde(rename = "commandId", default)]
+		command_id: Option<String>,
+	},
+	/// Undo a previous pause
+	Resume {
+		token: String,
+		#[serde(rename = "commandId", default)]
+		command_id: Option<String>,
+	},
+	/// Run an inline monitor once and report the outcome
+	Diagnose {
+		#[serde(rename = "requestId")]
+		request_id: String,
+		monitor: serde_json::Value,
+	},
+	/// Agent's reply to diagnose
+	DiagnoseResult {
+		#[serde(rename = "requestId")]
+		request_id: String,
+		success: bool,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		service: Option<String>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		result: Option<HashMap<String, f64>>,
+		/// Text results of the check, e.g. the TLS certificate issuer
+		#[serde(skip_serializing_if = "Option::is_none")]
+		labels: Option<HashMap<String, String>>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		timings: Option<DiagnoseTimings>,
+		#[serde(skip_serializing_if = "Option::is_none")]
+		error: Option<DiagnoseError>,
+		timestamp: String,
+	},
+	/// Periodic agent health report, sent when the server advertises `agent-status`
+	AgentStatus(AgentStatus),
+	/// Agent's reply to run-now, pause and resume
+	CommandAck {
+		#[serde(rename = "commandId", skip_serializing_if = "Option::is_none")]
+		command_id: Option<String>,
+		command: String,
+		token: String,
+		success: bool,
+		message: String,
+		timestamp: String,
+	},
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentHello {
+	pub version: String,
+	/// Service types this agent can check
+	pub services: Vec<String>,
+	/// Optional protocol features this agent supports
+	pub features: Vec<String>,
+	pub hostname: String,
+	pub os: String,
+	pub arch: String,
+	/// Persistent ID that survives restarts
+	pub instance_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AgentStatus {
+	/// Pulses waiting for acknowledgment on this connection
+	pub queue_depth: usize,
+	pub pulses_dropped_queue_full: u64,
+	pub pulses_dropped_max_retries: u64,
+	pub active_monitors: usize,
+	pub checks_per_second: f64,
+	pub scheduler_lag_ms: u64,
+	pub in_flight_checks: usize,
+	pub max_concurrent_checks: usize,
+	/// In-flight checks as a fraction of `maxConcurrentChecks`
+	pub semaphore_utilization: f64,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub memory_bytes: Option<u64>,
+	pub uptime_secs: u64,
+	pub timestamp: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnoseTimings {
+	pub start_time: String,
+	pub end_time: String,
+	pub duration_ms: f64,
+	pub latency_ms: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiagnoseError {
+	/// One of "invalid-monitor", "service-not-allowed", "busy" or "check-failed"
+	pub kind: String,
+	pub message: String,
 }
 
 impl WsMessage {
-	pub fn subscribe(token: &str) -> Self {
+	pub fn subscribe(token: &str, agent: &AgentHello) -> Self {
 		WsMessage::Subscribe {
 			token: token.to_string(),
+			agent: Some(agent.clone()),
 		}
 	}
+
+	pub fn command_ack(
+		command_id: Option<String>,
+		command: &str,
+		token: &str,
+		result: Result<String, String>,
+	) -> Self {
+		let (success, message) = match result {
+			Ok(message) => (true, message),
+			Err(message) => (false, message),
+		};
+		WsMessage::CommandAck {
+			command_id,
+			command: command.to_string(),
+			token: token.to_string(),
+			success,
+			message,
+			timestamp: chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn placeholder<'a>(placeholders: &'a [(String, String)], name: &str) -> Option<&'a str> {
+		placeholders
+			.iter()
+			.find(|(k, _)| k == name)
+			.map(|(_, v)| v.as_str())
+	}
+
+	#[test]
+	fn test_custom_metrics_mapping() {
+		let monitor = Monitor {
+			custom_metrics: Some(HashMap::from([
+				(CustomSlot::Custom1, "cpu".to_string()),
+				(CustomSlot::Custom2, "mem".to_string()),
+			])),
+			..Default::default()
+		};
+		let mut result = CheckResult::new();
+		result.set("cpu", 42.5);
+		result.set("mem", 73.0);
+
+		let placeholders = resolve_custom_placeholders(&monitor, &result);
+		assert_eq!(placeholder(&placeholders, "{custom1}"), Some("42.5"));
+		assert_eq!(placeholder(&placeholders, "{custom2}"), Some("73"));
+		assert_eq!(placeholder(&placeholders, "{custom3}"), Some(""));
+		assert_eq!(placeholder(&placeholders, "{cpu}"), Some("42.5"));
+
+		let push = PushMessage::new("token", None, None, None).with_custom_metrics(&monitor, &result);
+		assert_eq!(push.custom1, Some(42.5));
+		assert_eq!(push.custom2, Some(73.0));
+		assert_eq!(push.custom3, None);
+	}
+
+	#[test]
+	fn test_request_body_render() {
+		let http: HttpConfig = toml::from_str(
+			r#"
+			method = "POST"
+			url = "https://example.com"
+			body = { json = { at = "{now}", tags = ["{now}"], count = 2 } }
+			"#,
+		)
+		.unwrap();
+		let apply = |text: &str| text.replace("{now}", "123");
+		let (content_type, body) = http.body.unwrap().render(apply);
+		assert_eq!(content_type, "application/json");
+		assert_eq!(
+			serde_json::from_str::<serde_json::Value>(&body).unwrap(),
+			serde_json::json!({"at": "123", "tags": ["123"], "count": 2})
+		);
+
+		let form = RequestBody::Form {
+			form: HashMap::from([
+				("b".to_string(), "x y".to_string()),
+				("a".to_string(), "{now}&".to_string()),
+			]),
+		};
+		assert_eq!(
+			form.render(apply),
+			(
+				"application/x-www-form-urlencoded",
+				"a=123%26&b=x+y".to_string()
+			)
+		);
+
+		let raw: RequestBody = serde_json::from_str(r#""ping {now}""#).unwrap();
+		assert_eq!(raw.render(apply).1, "ping 123");
+	}
+
+	#[test]
+	fn test_custom_metrics_without_mapping() {
+		let monitor = Monitor::default();
+		let mut result = CheckResult::new();
+		result.set("custom1", 5.0);
+
+		let push = PushMessage::new("token", None, None, None).with_custom_metrics(&monitor, &result);
+		assert_eq!(push.custom1, Some(5.0));
+		assert_eq!(push.custom2, None);
+	}
+
+	#[test]
+	fn test_subscribe_hello_and_capabilities() {
+		let hello = AgentHello {
+			version: VERSION.to_string(),
+			services: vec!["http".to_string()],
+			features: vec!["diagnose".to_string()],
+			hostname: "probe-1".to_string(),
+			os: "linux".to_string(),
+			arch: "x86_64".to_string(),
+			instance_id: "5f0c".to_string(),
+		};
+		let json = serde_json::to_value(WsMessage::subscribe("tk_pulse", &hello)).unwrap();
+		assert_eq!(json["action"], "subscribe");
+		assert_eq!(json["agent"]["instanceId"], "5f0c");
+		assert_eq!(json["agent"]["features"][0], "diagnose");
+
+		let subscribed = r#"{"action": "subscribed", "pulseMonitorId": "eu", "pulseMonitorName": "EU",
+			"data": {"monitors": []}, "timestamp": "2025-01-21T07:06:00.000Z"}"#;
+		let WsMessage::Subscribed { capabilities, .. } = serde_json::from_str(subscribed).unwrap()
+		else {
+			panic!("expected subscribed");
+		};
+		assert!(capabilities.is_empty());
+
+		let subscribed =
+			subscribed.replace("\"data\"", "\"capabilities\": [\"agent-status\"], \"data\"");
+		let WsMessage::Subscribed { capabilities, .. } = serde_json::from_str(&subscribed).unwrap()
+		else {
+			panic!("expected subscribed");
+		};
+		assert_eq!(capabilities, vec!["agent-status"]);
+	}
+
+	#[test]
+	fn test_command_messages() {
+		let pause: WsMessage = serde_json::from_str(
+			r#"{"action": "pause", "token": "tk_api", "until": "2026-01-01T00:00:00Z", "commandId": "c1"}"#,
+		)
+		.unwrap();
+		assert!(matches!(
+			pause,
+			WsMessage::Pause { ref token, until: Some(_), command_id: Some(_) } if token == "tk_api"
+		));
+
+		let run_now: WsMessage =
+			serde_json::from_str(r#"{"action": "run-now", "token": "tk_api"}"#).unwrap();
+		assert!(matches!(
+			run_now,
+			WsMessage::RunNow {
+				command_id: None,
+				..
+			}
+		));
+
+		let ack = WsMessage::command_ack(
+			Some("c1".to_string()),
+			"resume",
+			"tk_api",
+			Err("Unknown monitor".to_string()),
+		);
+		let json = serde_json::to_value(&ack).unwrap();
+		assert_eq!(json["action"], "command-ack");
+		assert_eq!(json["commandId"], "c1");
+		assert_eq!(json["success"], false);
+		assert_eq!(json["message"], "Unknown monitor");
+	}
+
+	#[test]
+	fn test_state_dir() {
+		assert_eq!(
+			state_dir_from(Some("/home/pulse/.local/state".into())),
+			PathBuf::from("/home/pulse/.local/state/pulsemonitor")
+		);
+		assert_eq!(
+			state_dir_from(Some("relative/state".into())),
+			PathBuf::from(DEFAULT_STATE_DIR)
+		);
+		assert_eq!(state_dir_from(None), PathBuf::from(DEFAULT_STATE_DIR));
+	}
 }
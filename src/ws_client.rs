@@ -1,14 +1,30 @@
+use chrono::{DateTime, SecondsFormat, Utc};
 use futures_util::{SinkExt, StreamExt};
+use std::collections::HashSet;
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
+use std::time::Instant;
+use tokio::sync::{RwLock, mpsc, oneshot};
 use tokio::time::{Duration, sleep};
-use tokio_tungstenite::{connect_async, tungstenite::protocol::Message};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::handshake::client::Request;
+use tokio_tungstenite::tungstenite::http::header::{AUTHORIZATION, HeaderName, HeaderValue};
+use tokio_tungstenite::{Connector, tungstenite::protocol::Message};
 use tracing::{error, info, warn};
 
+use crate::agent::{agent_hello, resident_memory_bytes, started_at};
+use crate::config::check_remote_monitors;
+use crate::config_poller::parse_headers;
+use crate::diagnose::Diagnoser;
+use crate::monitor_runner::{CommandAction, CommandSender, SchedulerCommand, SchedulerStats};
+use crate::proxy;
 use crate::pulse_queue::{PulseQueue, PulseQueueConfig};
-use crate::utils::{Config, PushMessage, WsMessage};
+use crate::tls::server_tls_config;
+use crate::utils::{AgentStatus, Config, PushMessage, WsMessage};
 
 const RECONNECT_DELAY_SECS: u64 = 1;
+const DEFAULT_STATUS_INTERVAL_SECS: u64 = 60;
+/// Server capability that enables periodic `agent-status` messages
+const AGENT_STATUS_CAPABILITY: &str = "agent-status";
 
 /// Convert HTTP(S) URL to WebSocket URL
 fn http_to_ws_url(url: &str) -> String {
@@ -20,6 +36,21 @@ fn http_to_ws_url(url: &str) -> String {
 	format!("{}/ws", ws_url)
 }
 
+/// Parse `PULSE_SERVERS` ("url|token" entries separated by commas or newlines).
+pub fn parse_server_list(raw: &str) -> Vec<(String, String)> {
+	raw
+		.split([',', '\n'])
+		.filter_map(|entry| {
+			let (url, token) = entry.split_once('|')?;
+			let (url, token) = (url.trim(), token.trim());
+			if url.is_empty() || token.is_empty() {
+				return None;
+			}
+			Some((url.to_string(), token.to_string()))
+		})
+		.collect()
+}
+
 /// Channel for sending pulses through WebSocket
 pub type PulseSender = mpsc::Sender<PushMessage>;
 
@@ -30,6 +61,13 @@ pub struct WsClient {
 	pulse_queue: PulseQueue,
 	/// Shared sender for pulse messages
 	pulse_tx: Arc<RwLock<Option<mpsc::Sender<PushMessage>>>>,
+	/// Scheduler commands from this server, tagged with the origin of its monitors
+	commands: Option<(String, CommandSender)>,
+	/// Capability flags from the server's last `subscribed` message
+	server_capabilities: Arc<RwLock<HashSet<String>>>,
+	/// Scheduler counters included in `agent-status` reports
+	scheduler_stats: Option<Arc<SchedulerStats>>,
+	status_interval: Option<Duration>,
 }
 
 impl WsClient {
@@ -42,9 +80,30 @@ impl WsClient {
 			token: token.to_string(),
 			pulse_queue: PulseQueue::new(PulseQueueConfig::from_env()),
 			pulse_tx: Arc::new(RwLock::new(None)),
+			commands: None,
+			server_capabilities: Arc::new(RwLock::new(HashSet::new())),
+			scheduler_stats: None,
+			status_interval: status_interval_from_env(),
 		}
 	}
 
+	/// Report scheduler health to servers that support `agent-status`
+	pub fn with_status(mut self, stats: Arc<SchedulerStats>) -> Self {
+		self.scheduler_stats = Some(stats);
+		self
+	}
+
+	/// Whether the connected server advertised an optional protocol feature
+	pub async fn server_supports(&self, feature: &str) -> bool {
+		self.server_capabilities.read().await.contains(feature)
+	}
+
+	/// Forward run-now / pause / resume commands to the scheduler
+	pub fn with_commands(mut self, origin: &str, command_tx: CommandSender) -> Self {
+		self.commands = Some((origin.to_string(), command_tx));
+		self
+	}
+
 	/// Get a clone of the pulse sender for use by monitors
 	pub fn get_pulse_sender(&self) -> Arc<RwLock<Option<mpsc::Sender<PushMessage>>>> {
 		Arc::clone(&self.pulse_tx)
@@ -83,6 +142,7 @@ impl WsClient {
 				let mut tx = self.pulse_tx.write().await;
 				*tx = None;
 			}
+			self.server_capabilities.write().await.clear();
 
 			sleep(Duration::from_secs(RECONNECT_DELAY_SECS)).await;
 		}
@@ -94,19 +154,28 @@ impl WsClient {
 	) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 		info!("Connecting to WebSocket server: {}", self.ws_url);
 
-		let (ws_stream, _) = connect_async(&self.ws_url).await?;
+		let extra_headers = std::env::var("PULSE_WS_HEADERS")
+			.map(|raw| parse_headers(&raw))
+			.unwrap_or_default();
+		let request = upgrade_request(&self.ws_url, &self.token, &extra_headers)?;
+
+		let connector = server_tls_config()?.map(Connector::Rustls);
+		let (ws_stream, _) = proxy::connect_websocket(request, None, connector).await?;
 		let (mut write, mut read) = ws_stream.split();
 
 		info!("Connected to WebSocket server, subscribing...");
 
 		// Send subscribe message
-		let subscribe_msg = WsMessage::subscribe(&self.token);
+		let subscribe_msg = WsMessage::subscribe(&self.token, agent_hello());
 		let subscribe_json = serde_json::to_string(&subscribe_msg)?;
 		write.send(Message::Text(subscribe_json.into())).await?;
 
 		// Create channel for pulse messages
 		let (pulse_tx, mut pulse_rx) = mpsc::channel::<PushMessage>(4096);
 
+		// Replies produced outside this loop (command acks, diagnose results)
+		let (reply_tx, mut reply_rx) = mpsc::channel::<WsMessage>(64);
+
 		// Store the pulse sender for monitors to use
 		{
 			let mut tx = self.pulse_tx.write().await;
@@ -117,6 +186,16 @@ impl WsClient {
 
 		let retry_delay = self.pulse_queue.retry_delay();
 
+		// The first tick completes immediately, so reports start one interval after connecting
+		let mut status_tick =
+			tokio::time::interval(self.status_interval.unwrap_or(Duration::from_secs(3600)));
+		status_tick.tick().await;
+		// Checks started as of the previous status report, for checks/sec
+		let mut last_checks = self
+			.scheduler_stats
+			.as_ref()
+			.map(|stats| (stats.snapshot().checks_started, Instant::now()));
+
 		// Listen for messages and handle pulse sends
 		loop {
 			tokio::select! {
@@ -126,7 +205,7 @@ impl WsClient {
 						Some(Ok(Message::Text(text))) => {
 							match serde_json::from_str::<WsMessage>(&text) {
 								Ok(ws_msg) => {
-									if let Err(e) = self.handle_parsed_message(ws_msg, config_tx).await {
+									if let Err(e) = self.handle_parsed_message(ws_msg, config_tx, &reply_tx).await {
 										error!("Failed to handle WS message: {}", e);
 									}
 								}
@@ -182,6 +261,38 @@ impl WsClient {
 					}
 				}
 
+				// Send replies to server commands and diagnose requests
+				Some(reply) = reply_rx.recv() => {
+					match serde_json::to_string(&reply) {
+						Ok(json) => {
+							if let Err(e) = write.send(Message::Text(json.into())).await {
+								error!("Failed to send reply via WebSocket: {}", e);
+								break;
+							}
+						}
+						Err(e) => error!("Failed to serialize reply: {}", e),
+					}
+				}
+
+				// Periodic agent-status, only if the server understands it
+				_ = status_tick.tick(), if self.status_interval.is_some() => {
+					if !self.server_supports(AGENT_STATUS_CAPABILITY).await {
+						continue;
+					}
+					let Some(status) = self.agent_status(&mut last_checks).await else {
+						continue;
+					};
+					match serde_json::to_string(&WsMessage::AgentStatus(status)) {
+						Ok(json) => {
+							if let Err(e) = write.send(Message::Text(json.into())).await {
+								error!("Failed to send agent status: {}", e);
+								break;
+							}
+						}
+						Err(e) => error!("Failed to serialize agent status: {}", e),
+					}
+				}
+
 				// Retry timer -> periodically resend unacknowledged pulses
 				_ = sleep(retry_delay) => {
 					self.pulse_queue.prune_expired().await;
@@ -213,6 +324,7 @@ impl WsClient {
 		&self,
 		message: WsMessage,
 		config_tx: &mpsc::Sender<Config>,
+		reply_tx: &mpsc::Sender<WsMessage>,
 	) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
 		match message {
 			WsMessage::Connected { .. } => {}
@@ -221,13 +333,20 @@ impl WsClient {
 				pulse_monitor_id,
 				pulse_monitor_name,
 				data,
+				capabilities,
 				..
 			} => {
 				info!(
 					"Subscription successful: {} ({})",
 					pulse_monitor_name, pulse_monitor_id
 				);
+				if !capabilities.is_empty() {
+					info!("Server capabilities: {}", capabilities.join(", "));
+				}
+				*self.server_capabilities.write().await = capabilities.into_iter().collect();
 				info!("Received {} monitors from server", data.monitors.len());
+				check_remote_monitors(&data.monitors)
+					.map_err(|e| format!("Rejected configuration from server: {}", e))?;
 
 				let config = Config {
 					monitors: data.monitors,
@@ -253,6 +372,8 @@ impl WsClient {
 					"Received configuration update with {} monitors",
 					data.monitors.len()
 				);
+				check_remote_monitors(&data.monitors)
+					.map_err(|e| format!("Rejected configuration update: {}", e))?;
 
 				let config = Config {
 					monitors: data.monitors,
@@ -270,8 +391,54 @@ impl WsClient {
 				}
 			}
 
-			WsMessage::Subscribe { .. } => {
-				warn!("Received unexpected Subscribe message from server");
+			WsMessage::RunNow { token, command_id } => {
+				self.run_command(
+					"run-now",
+					token,
+					command_id,
+					Ok(CommandAction::RunNow),
+					reply_tx,
+				);
+			}
+
+			WsMessage::Pause {
+				token,
+				until,
+				command_id,
+			} => {
+				let action = parse_until(until.as_deref()).map(|until| CommandAction::Pause { until });
+				self.run_command("pause", token, command_id, action, reply_tx);
+			}
+
+			WsMessage::Resume { token, command_id } => {
+				self.run_command(
+					"resume",
+					token,
+					command_id,
+					Ok(CommandAction::Resume),
+					reply_tx,
+				);
+			}
+
+			WsMessage::Diagnose {
+				request_id,
+				monitor,
+			} => {
+				info!("Received diagnose request {}", request_id);
+				let reply_tx = reply_tx.clone();
+				tokio::spawn(async move {
+					let result = Diagnoser::shared().run(request_id, monitor).await;
+					if reply_tx.send(result).await.is_err() {
+						warn!("Connection closed before diagnose result could be sent");
+					}
+				});
+			}
+
+			WsMessage::Subscribe { .. }
+			| WsMessage::CommandAck { .. }
+			| WsMessage::DiagnoseResult { .. }
+			| WsMessage::AgentStatus(_) => {
+				warn!("Received unexpected client message from server");
 			}
 		}
 
@@ -279,6 +446,135 @@ impl WsClient {
 	}
 }
 
+/// Parse the optional RFC 3339 `until` of a pause command.
+fn parse_until(until: Option<&str>) -> Result<Option<DateTime<Utc>>, String> {
+	until
+		.map(|value| {
+			DateTime::parse_from_rfc3339(value)
+				.map(|t| t.with_timezone(&Utc))
+				.map_err(|e| format!("Invalid 'until' timestamp '{}': {}", value, e))
+		})
+		.transpose()
+}
+
+/// `PULSE_STATUS_INTERVAL` in seconds (default 60, 0 disables)
+fn status_interval_from_env() -> Option<Duration> {
+	let secs = std::env::var("PULSE_STATUS_INTERVAL")
+		.ok()
+		.and_then(|v| v.parse().ok())
+		.unwrap_or(DEFAULT_STATUS_INTERVAL_SECS);
+	(secs > 0).then(|| Duration::from_secs(secs))
+}
+
+impl WsClient {
+	/// Build an `agent-status` report; `last_checks` tracks the previous check count for the rate.
+	async fn agent_status(&self, last_checks: &mut Option<(u64, Instant)>) -> Option<AgentStatus> {
+		let scheduler = self.scheduler_stats.as_ref()?.snapshot();
+		let queue = self.pulse_queue.stats().await;
+
+		let now = Instant::now();
+		let checks_per_second = match last_checks.replace((scheduler.checks_started, now)) {
+			Some((previous, at)) if now > at => {
+				scheduler.checks_started.saturating_sub(previous) as f64
+					/ now.duration_since(at).as_secs_f64()
+			}
+			_ => 0.0,
+		};
+		let semaphore_utilization = if scheduler.max_concurrent > 0 {
+			scheduler.in_flight as f64 / scheduler.max_concurrent as f64
+		} else {
+			0.0
+		};
+
+		Some(AgentStatus {
+			queue_depth: queue.pending,
+			pulses_dropped_queue_full: queue.dropped_queue_full,
+			pulses_dropped_max_retries: queue.dropped_max_retries,
+			active_monitors: scheduler.active_monitors,
+			checks_per_second,
+			scheduler_lag_ms: scheduler.lag_ms,
+			in_flight_checks: scheduler.in_flight,
+			max_concurrent_checks: scheduler.max_concurrent,
+			semaphore_utilization,
+			memory_bytes: resident_memory_bytes(),
+			uptime_secs: started_at().elapsed().as_secs(),
+			timestamp: Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+		})
+	}
+
+	/// Send a command to the scheduler and ack its outcome without blocking the read loop.
+	fn run_command(
+		&self,
+		command: &'static str,
+		token: String,
+		command_id: Option<String>,
+		action: Result<CommandAction, String>,
+		reply_tx: &mpsc::Sender<WsMessage>,
+	) {
+		info!("Received '{}' command for monitor {}", command, token);
+
+		let commands = self.commands.clone();
+		let reply_tx = reply_tx.clone();
+
+		tokio::spawn(async move {
+			let result = match (action, commands) {
+				(Err(e), _) => Err(e),
+				(Ok(_), None) => Err("Commands are not supported by this agent".to_string()),
+				(Ok(action), Some((origin, command_tx))) => {
+					let (reply, response) = oneshot::channel();
+					let sent = command_tx
+						.send(SchedulerCommand {
+							origin: Some(origin),
+							token: token.clone(),
+							action,
+							reply,
+						})
+						.await;
+					match sent {
+						Ok(()) => response
+							.await
+							.unwrap_or_else(|_| Err("Scheduler is not running".to_string())),
+						Err(_) => Err("Scheduler is not running".to_string()),
+					}
+				}
+			};
+
+			if let Err(e) = &result {
+				warn!("Command '{}' for monitor {} failed: {}", command, token, e);
+			}
+
+			let ack = WsMessage::command_ack(command_id, command, &token, result);
+			if reply_tx.send(ack).await.is_err() {
+				warn!(
+					"Connection closed before '{}' command could be acknowledged",
+					command
+				);
+			}
+		});
+	}
+}
+
+/// WebSocket upgrade request with `extra_headers` (from `PULSE_WS_HEADERS`) and the agent token.
+/// The token also goes in the upgrade request so proxies and the server can authenticate early;
+/// it takes precedence over an extra `Authorization` header.
+fn upgrade_request(
+	url: &str,
+	token: &str,
+	extra_headers: &[(String, String)],
+) -> Result<Request, Box<dyn std::error::Error + Send + Sync>> {
+	let mut request = url.into_client_request()?;
+	let headers = request.headers_mut();
+	for (name, value) in extra_headers {
+		let name = HeaderName::from_bytes(name.as_bytes())
+			.map_err(|_| format!("Invalid header name in PULSE_WS_HEADERS: '{}'", name))?;
+		let value = HeaderValue::from_str(value)
+			.map_err(|_| format!("Invalid value for header '{}' in PULSE_WS_HEADERS", name))?;
+		headers.append(name, value);
+	}
+	headers.insert(AUTHORIZATION, format!("Bearer {}", token).parse()?);
+	Ok(request)
+}
+
 #[cfg(test)]
 mod tests {
 	use super::*;
@@ -298,4 +594,66 @@ mod tests {
 			"ws://localhost:3000/ws"
 		);
 	}
+
+	#[test]
+	fn test_upgrade_request_headers() {
+		let extra =
+			parse_headers("X-Agent-Region: eu-west; Authorization: Basic ignored; X-Tag: a; X-Tag: b");
+		let request = upgrade_request("wss://pulse.example.com/ws", "tk_agent", &extra).unwrap();
+		let headers = request.headers();
+		assert_eq!(headers["x-agent-region"], "eu-west");
+		assert_eq!(headers.get_all("x-tag").iter().count(), 2);
+		assert_eq!(headers[AUTHORIZATION], "Bearer tk_agent");
+
+		let invalid = parse_headers("Bad Header: x");
+		assert_eq!(
+			upgrade_request("wss://pulse.example.com/ws", "tk_agent", &invalid)
+				.unwrap_err()
+				.to_string(),
+			"Invalid header name in PULSE_WS_HEADERS: 'Bad Header'"
+		);
+	}
+
+	#[test]
+	fn test_parse_server_list() {
+		assert_eq!(
+			parse_server_list("https://eu.example.com|tk_eu, https://us.example.com | tk_us\nbroken"),
+			vec![
+				("https://eu.example.com".to_string(), "tk_eu".to_string()),
+				("https://us.example.com".to_string(), "tk_us".to_string()),
+			]
+		);
+		assert!(parse_server_list("").is_empty());
+	}
+
+	#[tokio::test]
+	async fn test_agent_status() {
+		let client = WsClient::new("http://localhost:3000", "tk_pulse");
+		assert!(client.agent_status(&mut None).await.is_none());
+
+		let client = client.with_status(Arc::new(SchedulerStats::default()));
+		let mut last_checks = None;
+		let status = client.agent_status(&mut last_checks).await.unwrap();
+		assert_eq!(status.queue_depth, 0);
+		assert_eq!(status.checks_per_second, 0.0);
+		assert_eq!(status.semaphore_utilization, 0.0);
+		assert!(last_checks.is_some());
+
+		let json = serde_json::to_value(WsMessage::AgentStatus(status)).unwrap();
+		assert_eq!(json["action"], "agent-status");
+		assert_eq!(json["activeMonitors"], 0);
+	}
+
+	#[test]
+	fn test_parse_until() {
+		assert_eq!(parse_until(None), Ok(None));
+		assert_eq!(
+			parse_until(Some("2026-03-01T12:00:00+02:00"))
+				.unwrap()
+				.unwrap()
+				.to_rfc3339(),
+			"2026-03-01T10:00:00+00:00"
+		);
+		assert!(parse_until(Some("tomorrow")).is_err());
+	}
 }